@@ -0,0 +1,263 @@
+// The swarm NetworkBehaviour of the scope and the pubsub options used to build it.
+// Floodsub and gossipsub are both part of the behaviour but only the one selected with --pubsub
+// is enabled, the other is a disabled Toggle. The helpers on AppBehaviour hide which one is in
+// use so the main loop and the UI work the same with either.
+
+use std::collections::hash_map::DefaultHasher;
+use std::convert::Infallible;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use base64::Engine;
+
+use libp2p::floodsub::{self, Floodsub, FloodsubEvent};
+use libp2p::gossipsub;
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{identity, mdns, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::dial_targets::DialFailures;
+
+// We create a custom behaviour that combines pubsub and mDNS, dial_failures only watches dials.
+// The derive generates a delegating `NetworkBehaviour` impl.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "AppBehaviourEvent")]
+pub struct AppBehaviour {
+    floodsub: Toggle<Floodsub>,
+    gossipsub: Toggle<gossipsub::Behaviour>,
+    pub mdns: mdns::tokio::Behaviour,
+    pub dial_failures: DialFailures,
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum AppBehaviourEvent {
+    Floodsub(FloodsubEvent),
+    Gossipsub(gossipsub::Event),
+    Mdns(mdns::Event),
+}
+
+impl From<FloodsubEvent> for AppBehaviourEvent {
+    fn from(event: FloodsubEvent) -> Self {
+        AppBehaviourEvent::Floodsub(event)
+    }
+}
+
+impl From<gossipsub::Event> for AppBehaviourEvent {
+    fn from(event: gossipsub::Event) -> Self {
+        AppBehaviourEvent::Gossipsub(event)
+    }
+}
+
+impl From<mdns::Event> for AppBehaviourEvent {
+    fn from(event: mdns::Event) -> Self {
+        AppBehaviourEvent::Mdns(event)
+    }
+}
+
+impl From<Infallible> for AppBehaviourEvent {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
+
+impl AppBehaviour {
+    pub fn new(
+        id_keys: &identity::Keypair,
+        pubsub_mode: PubsubMode,
+        gossipsub_options: &GossipsubOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        let peer_id = PeerId::from(id_keys.public());
+        let (floodsub, gossipsub) = match pubsub_mode {
+            PubsubMode::Floodsub => (Some(Floodsub::new(peer_id)), None),
+            PubsubMode::Gossipsub => (None, Some(gossipsub_options.behaviour(id_keys)?)),
+        };
+        Ok(AppBehaviour {
+            floodsub: floodsub.into(),
+            gossipsub: gossipsub.into(),
+            mdns: mdns::Behaviour::new(Default::default(), peer_id)?,
+            dial_failures: DialFailures::default(),
+        })
+    }
+
+    pub fn subscribe(&mut self, topic: &str) -> Result<bool, Box<dyn Error>> {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            return Ok(floodsub.subscribe(floodsub::Topic::new(topic)));
+        }
+        if let Some(gossipsub) = self.gossipsub.as_mut() {
+            return Ok(gossipsub.subscribe(&gossipsub::IdentTopic::new(topic))?);
+        }
+        Ok(false)
+    }
+
+    pub fn unsubscribe(&mut self, topic: &str) -> Result<bool, Box<dyn Error>> {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            return Ok(floodsub.unsubscribe(floodsub::Topic::new(topic)));
+        }
+        if let Some(gossipsub) = self.gossipsub.as_mut() {
+            return Ok(gossipsub.unsubscribe(&gossipsub::IdentTopic::new(topic))?);
+        }
+        Ok(false)
+    }
+
+    // Gossipsub refuses to publish without peers subscribed to the topic, floodsub silently
+    // drops the message.
+    pub fn publish(&mut self, topic: &str, data: impl Into<Vec<u8>>) -> Result<(), Box<dyn Error>> {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            floodsub.publish_any(floodsub::Topic::new(topic), data);
+        } else if let Some(gossipsub) = self.gossipsub.as_mut() {
+            gossipsub.publish(gossipsub::IdentTopic::new(topic), data)?;
+        }
+        Ok(())
+    }
+
+    // Floodsub only sends to peers in its partial view, gossipsub tracks connections itself.
+    pub fn peer_connected(&mut self, peer_id: PeerId) {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            floodsub.add_node_to_partial_view(peer_id);
+        }
+    }
+
+    pub fn peer_disconnected(&mut self, peer_id: &PeerId) {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            floodsub.remove_node_from_partial_view(peer_id);
+        }
+    }
+
+    pub fn peer_discovered(&mut self, peer_id: PeerId) {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            floodsub.add_node_to_partial_view(peer_id);
+        }
+        if let Some(gossipsub) = self.gossipsub.as_mut() {
+            gossipsub.add_explicit_peer(&peer_id);
+        }
+    }
+
+    pub fn peer_expired(&mut self, peer_id: &PeerId) {
+        if let Some(floodsub) = self.floodsub.as_mut() {
+            floodsub.remove_node_from_partial_view(peer_id);
+        }
+        if let Some(gossipsub) = self.gossipsub.as_mut() {
+            gossipsub.remove_explicit_peer(peer_id);
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PubsubMode {
+    Floodsub,
+    #[default]
+    Gossipsub,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GossipsubValidation {
+    /// Messages must be signed and carry a source and sequence number.
+    #[default]
+    Strict,
+    /// Signatures are checked when present.
+    Permissive,
+    /// Messages are published without source, sequence number or signature.
+    Anonymous,
+    /// No checks at all.
+    None,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GossipsubMessageId {
+    /// Source PeerId and sequence number, the gossipsub default.
+    #[default]
+    SourceSeqno,
+    /// Hash of the message data like the chat example, identical messages are de-duplicated.
+    ContentHash,
+}
+
+// Gossipsub settings, unset values keep the libp2p defaults.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct GossipsubOptions {
+    #[arg(long = "gossipsub-heartbeat", value_name = "MILLISECONDS")]
+    /// Gossipsub heartbeat interval.
+    pub heartbeat_ms: Option<u64>,
+    #[arg(long = "gossipsub-d", value_name = "D")]
+    /// Target number of peers in the mesh for each topic.
+    pub mesh_n: Option<usize>,
+    #[arg(long = "gossipsub-d-lo", value_name = "D_LO")]
+    /// Fewer mesh peers than this triggers grafting more.
+    pub mesh_n_low: Option<usize>,
+    #[arg(long = "gossipsub-d-hi", value_name = "D_HI")]
+    /// More mesh peers than this triggers pruning.
+    pub mesh_n_high: Option<usize>,
+    #[arg(long = "gossipsub-validation", value_enum, default_value_t)]
+    pub validation: GossipsubValidation,
+    #[arg(long = "gossipsub-message-id", value_enum, default_value_t)]
+    pub message_id: GossipsubMessageId,
+}
+
+impl GossipsubOptions {
+    pub fn config(&self) -> Result<gossipsub::Config, Box<dyn Error>> {
+        let mut builder = gossipsub::ConfigBuilder::default();
+        if let Some(heartbeat_ms) = self.heartbeat_ms {
+            builder.heartbeat_interval(Duration::from_millis(heartbeat_ms));
+        }
+        let defaults = gossipsub::Config::default();
+        let mesh_n = self.mesh_n.unwrap_or(defaults.mesh_n());
+        let mesh_n_low = self.mesh_n_low.unwrap_or(defaults.mesh_n_low().min(mesh_n));
+        let mesh_n_high = self.mesh_n_high.unwrap_or(defaults.mesh_n_high().max(mesh_n));
+        builder
+            .mesh_n(mesh_n)
+            .mesh_n_low(mesh_n_low)
+            .mesh_n_high(mesh_n_high)
+            // small test meshes would otherwise fail validation of the outbound minimum
+            .mesh_outbound_min(defaults.mesh_outbound_min().min(mesh_n_low).min(mesh_n / 2))
+            .validation_mode(match self.validation {
+                GossipsubValidation::Strict => gossipsub::ValidationMode::Strict,
+                GossipsubValidation::Permissive => gossipsub::ValidationMode::Permissive,
+                GossipsubValidation::Anonymous => gossipsub::ValidationMode::Anonymous,
+                GossipsubValidation::None => gossipsub::ValidationMode::None,
+            });
+        if self.message_id == GossipsubMessageId::ContentHash {
+            builder.message_id_fn(|message: &gossipsub::Message| {
+                let mut s = DefaultHasher::new();
+                message.data.hash(&mut s);
+                gossipsub::MessageId::from(s.finish().to_string())
+            });
+        }
+        Ok(builder
+            .build()
+            .map_err(|e| format!("Invalid gossipsub configuration: {e}"))?)
+    }
+
+    fn behaviour(&self, id_keys: &identity::Keypair) -> Result<gossipsub::Behaviour, Box<dyn Error>> {
+        let authenticity = match self.validation {
+            GossipsubValidation::Anonymous => gossipsub::MessageAuthenticity::Anonymous,
+            _ => gossipsub::MessageAuthenticity::Signed(id_keys.clone()),
+        };
+        Ok(gossipsub::Behaviour::new(authenticity, self.config()?)?)
+    }
+}
+
+// Longest part of a binary payload shown in the chat
+const BINARY_PREVIEW_BYTES: usize = 48;
+
+// Chat text of a pubsub payload. Peers may publish anything, a payload that is not UTF-8 is
+// shown as a base64 preview.
+pub fn message_text(data: Vec<u8>) -> String {
+    match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) => {
+            let data = e.into_bytes();
+            let preview = &data[..data.len().min(BINARY_PREVIEW_BYTES)];
+            format!(
+                "[binary, {} bytes] base64:{}{}",
+                data.len(),
+                base64::engine::general_purpose::STANDARD.encode(preview),
+                if preview.len() < data.len() { "..." } else { "" }
+            )
+        }
+    }
+}
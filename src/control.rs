@@ -0,0 +1,448 @@
+// Control socket for driving a running node from another terminal or a script.
+// A Unix domain socket speaking JSON-RPC 2.0 with one message per line. The methods are the
+// runtime commands, params are given by position or by name:
+//   {"jsonrpc":"2.0","id":1,"method":"dial","params":["/ip4/127.0.0.1/tcp/4001"]}
+//   {"jsonrpc":"2.0","id":2,"method":"publish","params":{"topic":"monolith","message":"hi"}}
+//   {"jsonrpc":"2.0","id":3,"method":"peers"}
+// `events` streams every swarm event as an `event` notification and `updates` every UiUpdate
+// as an `update` notification until the connection closes. `updates` starts with the current
+// state of the node so an attached TUI can catch up.
+// The control socket is a frontend like the TUI, `p2p-scope ctl` and `attach` are its clients.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::frontend::{CommandSender, NodeInfo, ScopeFrontend};
+use crate::ui_api::{StateQuery, UiCommand, UiUpdate};
+use crate::update_stream::UpdateStream;
+use crate::{CliArguments, CliCommand};
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// The node rejected the command
+pub const COMMAND_FAILED: i64 = -32000;
+
+
+// e.g. /run/user/1000/p2p-scope/control.sock on Linux
+pub fn default_socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("p2p-scope")
+        .join("control.sock")
+}
+
+// The socket picked with --control or the daemon subcommand, if any
+pub fn control_socket(args: &CliArguments) -> Option<PathBuf> {
+    if let Some(CliCommand::Daemon(daemon)) = &args.command {
+        return Some(daemon.socket.clone().unwrap_or_else(default_socket_path));
+    }
+    args.control
+        .as_ref()
+        .map(|path| path.clone().unwrap_or_else(default_socket_path))
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub(crate) struct CtlCommand {
+    #[arg(long, value_name = "PATH")]
+    /// Control socket of the node. Defaults to the socket --control uses without a path.
+    socket: Option<PathBuf>,
+    /// dial, stick, unstick, listen, hang_up, publish, subscribe, unsubscribe, peers, listeners,
+    /// topics, node or shutdown. events and updates print the stream of the node until it stops.
+    method: String,
+    /// Parameters of the method in order, e.g. `ctl publish monolith hello`.
+    params: Vec<String>,
+}
+
+pub struct ControlFrontend {
+    path: PathBuf,
+    updates: UpdateStream,
+    accept_task: Option<JoinHandle<()>>,
+}
+
+impl ControlFrontend {
+    pub fn new(path: PathBuf) -> Self {
+        ControlFrontend {
+            path,
+            updates: UpdateStream::default(),
+            accept_task: None,
+        }
+    }
+}
+
+#[async_trait]
+impl ScopeFrontend for ControlFrontend {
+    fn name(&self) -> &'static str {
+        "control socket"
+    }
+
+    async fn start(
+        &mut self,
+        node: &NodeInfo,
+        commands: CommandSender,
+    ) -> Result<(), Box<dyn Error>> {
+        let listener = bind(&self.path)?;
+        let node = json!({
+            "peer_id": node.peer_id,
+            "identity": node.identity_source.to_string(),
+        });
+        let updates = self.updates.clone();
+        self.accept_task = Some(tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, commands.clone(), updates.clone(), node.clone()));
+            }
+        }));
+        Ok(())
+    }
+
+    // Nobody streaming is not an error
+    fn update(&self, update: UiUpdate) -> Result<(), Box<dyn Error>> {
+        self.updates.send(update);
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        if let Some(accept_task) = self.accept_task.take() {
+            accept_task.abort();
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+// A socket file left behind by a node that did not stop cleanly is replaced,
+// one that still answers belongs to another node.
+fn bind(path: &Path) -> Result<UnixListener, Box<dyn Error>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    check_private_dir(dir)?;
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{} exists and is not a socket", path.display()).into());
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(format!(
+                "Control socket {} is in use by another node, give --control=PATH another path",
+                path.display()
+            )
+            .into());
+        }
+        fs::remove_file(path)?;
+    }
+    // anyone who can connect can drive the node, the socket is created for the owner only
+    // SAFETY: umask has no preconditions, the old mask is put back right after
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    Ok(listener.map_err(|e| format!("Can not create control socket {}: {e}", path.display()))?)
+}
+
+// Someone else who can write to the directory could replace the socket with their own,
+// so it has to be ours and closed to everyone else. The default one is created that way.
+fn check_private_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let metadata = fs::symlink_metadata(dir)
+        .map_err(|e| format!("Can not check the control socket directory {}: {e}", dir.display()))?;
+    // SAFETY: geteuid has no preconditions and can not fail
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(format!(
+            "The control socket directory {} must be a directory of your own with mode 0700, \
+             use e.g. chmod 700 or give --control=PATH in another directory",
+            dir.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    // Notifications have no id and get no response
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+// What a connection is streaming, set by the events and updates methods
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stream {
+    Events,
+    Updates,
+}
+
+// Requests are answered in order, a streaming connection can still send requests
+async fn serve(stream: UnixStream, commands: CommandSender, updates: UpdateStream, node: Value) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut streaming: Option<(Stream, broadcast::Receiver<UiUpdate>)> = None;
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    return;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let request = match serde_json::from_str::<Request>(&line) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let code = if serde_json::from_str::<Value>(&line).is_ok() {
+                            INVALID_REQUEST
+                        } else {
+                            PARSE_ERROR
+                        };
+                        let error = RpcError::new(code, e.to_string());
+                        if send_response(&mut writer, Value::Null, Err(error)).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                // sent after the response to `updates`
+                let mut snapshot = Vec::new();
+                let outcome = match request.method.as_str() {
+                    "events" => {
+                        streaming = Some((Stream::Events, updates.subscribe()));
+                        Ok(Value::Bool(true))
+                    }
+                    "updates" => {
+                        let (state, receiver) = updates.subscribe_with_state();
+                        snapshot = state;
+                        streaming = Some((Stream::Updates, receiver));
+                        Ok(Value::Bool(true))
+                    }
+                    "node" => {
+                        let mut node = node.clone();
+                        node["topics"] = json!(updates.topics());
+                        Ok(node)
+                    }
+                    "shutdown" => {
+                        let _ = commands.send(UiCommand::Shutdown).await;
+                        Ok(Value::Null)
+                    }
+                    method => match command_for(method, &request.params) {
+                        Ok(command) => commands
+                            .request(command)
+                            .await
+                            .map(reply_result)
+                            .map_err(|e| RpcError::new(COMMAND_FAILED, e)),
+                        Err(e) => Err(e),
+                    },
+                };
+                if let Some(id) = request.id {
+                    if send_response(&mut writer, id, outcome).await.is_err() {
+                        return;
+                    }
+                }
+                for update in snapshot {
+                    let notification = json!({"jsonrpc": "2.0", "method": "update", "params": update});
+                    if write_line(&mut writer, &notification).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            update = next_update(&mut streaming) => {
+                let notification = match update {
+                    Ok((Stream::Events, UiUpdate::Event(event))) => {
+                        json!({"jsonrpc": "2.0", "method": "event", "params": event})
+                    }
+                    Ok((Stream::Events, _)) => continue,
+                    Ok((Stream::Updates, update)) => {
+                        json!({"jsonrpc": "2.0", "method": "update", "params": update})
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        json!({"jsonrpc": "2.0", "method": "lagged", "params": {"skipped": skipped}})
+                    }
+                    // the node is shutting down
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                if write_line(&mut writer, &notification).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Never ready when the connection is not streaming
+async fn next_update(
+    streaming: &mut Option<(Stream, broadcast::Receiver<UiUpdate>)>,
+) -> Result<(Stream, UiUpdate), broadcast::error::RecvError> {
+    match streaming {
+        Some((kind, receiver)) => receiver.recv().await.map(|update| (*kind, update)),
+        None => std::future::pending().await,
+    }
+}
+
+fn command_for(method: &str, params: &Value) -> Result<UiCommand, RpcError> {
+    let command = match method {
+        "dial" => UiCommand::Dial(param(params, 0, "address")?),
+        "stick" => UiCommand::Stick(param(params, 0, "address")?),
+        "unstick" => UiCommand::Unstick(param(params, 0, "address")?),
+        "listen" => UiCommand::Listen(param(params, 0, "address")?),
+        "hang_up" => UiCommand::HangUp(param(params, 0, "peer")?),
+        "publish" => UiCommand::Publish {
+            topic: param(params, 0, "topic")?,
+            message: param(params, 1, "message")?,
+        },
+        "subscribe" => UiCommand::Subscribe(param(params, 0, "topic")?),
+        "unsubscribe" => UiCommand::Unsubscribe(param(params, 0, "topic")?),
+        "peers" => UiCommand::Query(StateQuery::Peers),
+        "listeners" => UiCommand::Query(StateQuery::Listeners),
+        "topics" => UiCommand::Query(StateQuery::Topics),
+        _ => return Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+    };
+    Ok(command)
+}
+
+// The method and params of a command, the reverse of command_for.
+// None for the commands that have no method.
+pub fn request_for(command: &UiCommand) -> Option<(&'static str, Value)> {
+    let request = match command {
+        UiCommand::Dial(address) => ("dial", json!([address])),
+        UiCommand::Stick(address) => ("stick", json!([address])),
+        UiCommand::Unstick(address) => ("unstick", json!([address])),
+        UiCommand::Listen(address) => ("listen", json!([address])),
+        UiCommand::HangUp(peer_id) => ("hang_up", json!([peer_id])),
+        UiCommand::Publish { topic, message } => ("publish", json!([topic, message])),
+        UiCommand::Subscribe(topic) => ("subscribe", json!([topic])),
+        UiCommand::Unsubscribe(topic) => ("unsubscribe", json!([topic])),
+        UiCommand::Query(StateQuery::Peers) => ("peers", json!([])),
+        UiCommand::Query(StateQuery::Listeners) => ("listeners", json!([])),
+        UiCommand::Query(StateQuery::Topics) => ("topics", json!([])),
+        UiCommand::Shutdown | UiCommand::Replay(_) => return None,
+    };
+    Some(request)
+}
+
+// A string parameter by position or by name
+fn param<T>(params: &Value, index: usize, name: &str) -> Result<T, RpcError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = match params {
+        Value::Array(values) => values.get(index),
+        Value::Object(values) => values.get(name),
+        _ => None,
+    };
+    let Some(Value::String(text)) = value else {
+        return Err(RpcError::new(INVALID_PARAMS, format!("Missing string parameter {name}")));
+    };
+    text.parse()
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid {name} {text}: {e}")))
+}
+
+// The replies of ui_api.rs without the UiUpdate wrapper
+fn reply_result(reply: Option<UiUpdate>) -> Value {
+    match reply {
+        None => Value::Null,
+        Some(UiUpdate::CommandResult(text)) => json!(text),
+        Some(UiUpdate::Subscribed(topic)) | Some(UiUpdate::Unsubscribed(topic)) => json!(topic),
+        Some(UiUpdate::DialTargetChanged(target)) => json!(target),
+        Some(UiUpdate::DialTargetRemoved(address)) => json!(address),
+        Some(UiUpdate::PeerList(peers)) => json!(peers),
+        Some(UiUpdate::ListenerList(addresses)) => json!(addresses),
+        Some(UiUpdate::TopicList(topics)) => json!(topics),
+        Some(other) => json!(other),
+    }
+}
+
+async fn send_response(
+    writer: &mut OwnedWriteHalf,
+    id: Value,
+    outcome: Result<Value, RpcError>,
+) -> io::Result<()> {
+    let response = match outcome {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(RpcError { code, message }) => {
+            json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+        }
+    };
+    write_line(writer, &response).await
+}
+
+pub async fn write_line(writer: &mut OwnedWriteHalf, message: &Value) -> io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+// ctl subcommand, sends one request and prints the result.
+// A string result is printed as is, anything else as JSON. The streams print one
+// JSON value per line until the node stops.
+pub async fn run_ctl(ctl: CtlCommand) -> Result<(), Box<dyn Error>> {
+    let path = ctl.socket.unwrap_or_else(default_socket_path);
+    let (reader, mut writer) = connect(&path).await?.into_split();
+    let mut request = json!({"jsonrpc": "2.0", "id": 1, "method": ctl.method});
+    if !ctl.params.is_empty() {
+        request["params"] = json!(ctl.params);
+    }
+    write_line(&mut writer, &request).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut stdout = io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        let message: Value = serde_json::from_str(&line)?;
+        if let Some(error) = message.get("error") {
+            let text = error["message"].as_str().unwrap_or("unknown error");
+            return Err(format!("{} failed: {text}", ctl.method).into());
+        }
+        let streaming = matches!(ctl.method.as_str(), "events" | "updates");
+        let output = match (message.get("result"), message.get("params")) {
+            // the stream follows
+            (Some(_), _) if streaming => continue,
+            (Some(Value::Null), _) => return Ok(()),
+            (Some(Value::String(text)), _) => text.clone(),
+            (Some(result), _) => result.to_string(),
+            (None, Some(params)) => params.to_string(),
+            (None, None) => continue,
+        };
+        // a closed pipe, e.g. into head, ends the stream
+        if writeln!(stdout, "{output}").is_err() || !streaming {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+pub async fn connect(path: &Path) -> Result<UnixStream, Box<dyn Error>> {
+    let stream = UnixStream::connect(path).await.map_err(|e| {
+        format!("Can not connect to {}: {e}, is a node running with --control?", path.display())
+    })?;
+    Ok(stream)
+}
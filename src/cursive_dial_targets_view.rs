@@ -0,0 +1,129 @@
+// Sticky dial targets table for the cursive TUI, built on cursive_table_view.
+// Rows are DialTargetInfo records kept up to date by UiUpdate::DialTargetChanged.
+// The table does not tick, a target backing off shows the time of its next attempt.
+// Enter on a row shows the last error and offers to stop redialing.
+
+use std::cmp::Ordering;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+use cursive_table_view::{TableView, TableViewItem};
+use libp2p::Multiaddr;
+
+use crate::cursive_events_view::format_time_of_day;
+use crate::cursive_tui::send_command;
+use crate::dial_targets::{DialTargetInfo, DialTargetState};
+use crate::ui_api::UiCommand;
+
+const DIAL_TARGETS_TABLE: &str = "dial_targets_table";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DialTargetColumn {
+    Address,
+    State,
+    Failures,
+}
+
+impl TableViewItem<DialTargetColumn> for DialTargetInfo {
+    fn to_column(&self, column: DialTargetColumn) -> String {
+        match column {
+            DialTargetColumn::Address => self.address.to_string(),
+            DialTargetColumn::State => match &self.state {
+                DialTargetState::BackingOff { next_attempt } => {
+                    format!("retry {}", &format_time_of_day(*next_attempt)[..8])
+                }
+                state => state.to_string(),
+            },
+            DialTargetColumn::Failures => self.failures.to_string(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: DialTargetColumn) -> Ordering {
+        match column {
+            DialTargetColumn::Address => self.address.to_string().cmp(&other.address.to_string()),
+            DialTargetColumn::State => self.to_column(column).cmp(&other.to_column(column)),
+            DialTargetColumn::Failures => self.failures.cmp(&other.failures),
+        }
+    }
+}
+
+pub fn dial_targets_table() -> impl View {
+    TableView::<DialTargetInfo, DialTargetColumn>::new()
+        .column(DialTargetColumn::Address, "Address", |c| c)
+        .column(DialTargetColumn::State, "State", |c| c.width(15))
+        .column(DialTargetColumn::Failures, "Fails", |c| c.width(8))
+        .default_column(DialTargetColumn::Address)
+        .on_submit(|s: &mut Cursive, _row: usize, index: usize| {
+            let target = s.call_on_name(
+                DIAL_TARGETS_TABLE,
+                |table: &mut TableView<DialTargetInfo, DialTargetColumn>| {
+                    table.borrow_item(index).cloned()
+                },
+            );
+            if let Some(Some(target)) = target {
+                dlg_dial_target_actions(s, target);
+            }
+        })
+        .with_name(DIAL_TARGETS_TABLE)
+        .min_height(5)
+}
+
+pub fn dial_target_changed(s: &mut Cursive, target: DialTargetInfo) {
+    s.call_on_name(
+        DIAL_TARGETS_TABLE,
+        |table: &mut TableView<DialTargetInfo, DialTargetColumn>| {
+            let existing = table.borrow_items().iter().position(|t| t.address == target.address);
+            match existing {
+                Some(index) => {
+                    if let Some(item) = table.borrow_item_mut(index) {
+                        *item = target;
+                    }
+                    table.sort();
+                }
+                None => table.insert_item(target),
+            }
+        },
+    );
+}
+
+pub fn dial_target_removed(s: &mut Cursive, address: Multiaddr) {
+    s.call_on_name(
+        DIAL_TARGETS_TABLE,
+        |table: &mut TableView<DialTargetInfo, DialTargetColumn>| {
+            let existing = table.borrow_items().iter().position(|t| t.address == address);
+            if let Some(index) = existing {
+                table.remove_item(index);
+            }
+        },
+    );
+}
+
+fn dlg_dial_target_actions(s: &mut Cursive, target: DialTargetInfo) {
+    let mut details = format!(
+        "{}\n\nState: {} since {} UTC\nFailed dials: {}",
+        target.address,
+        target.state,
+        format_time_of_day(target.since),
+        target.failures,
+    );
+    if let DialTargetState::BackingOff { next_attempt } = &target.state {
+        details.push_str(&format!("\nNext attempt: {} UTC", format_time_of_day(*next_attempt)));
+    }
+    if let Some(peer_id) = &target.peer_id {
+        details.push_str(&format!("\nPeer ID: {}", peer_id));
+    }
+    if let Some(error) = &target.last_error {
+        details.push_str(&format!("\nLast error: {}", error));
+    }
+    let address = target.address;
+    s.add_layer(
+        Dialog::around(TextView::new(details))
+            .title("Dial Target")
+            .button("Stop redialing", move |s| {
+                send_command(s, UiCommand::Unstick(address.clone()));
+                s.pop_layer();
+            })
+            .dismiss_button("Close"),
+    );
+}
@@ -0,0 +1,193 @@
+// Event log table for the cursive TUI, built on cursive_table_view.
+// Every ScopeEvent is kept in EventHistory in the user data, the table shows the ones that
+// pass the category and peer filters above it. Enter on a row shows the raw event.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+use cursive_table_view::{TableView, TableViewItem};
+
+use crate::cursive_tui::TheApiUserData;
+use crate::scope_event::{EventCategory, ScopeEvent};
+
+const EVENTS_TABLE: &str = "events_table";
+// Older events are dropped so a long running scope does not grow without bound
+const MAX_EVENTS: usize = 5000;
+
+#[derive(Debug, Default)]
+pub(crate) struct EventHistory {
+    events: VecDeque<ScopeEvent>,
+    category: Option<EventCategory>,
+    // Part of a peer id, empty shows all
+    peer: String,
+}
+
+impl EventHistory {
+    fn matches(&self, event: &ScopeEvent) -> bool {
+        if self.category.is_some_and(|c| c != event.category) {
+            return false;
+        }
+        self.peer.is_empty()
+            || event.peer.is_some_and(|p| p.to_string().contains(&self.peer))
+    }
+
+    fn filtered(&self) -> Vec<ScopeEvent> {
+        self.events.iter().filter(|e| self.matches(e)).cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventColumn {
+    Time,
+    Delta,
+    Category,
+    Peer,
+    Connection,
+    Summary,
+}
+
+impl TableViewItem<EventColumn> for ScopeEvent {
+    fn to_column(&self, column: EventColumn) -> String {
+        match column {
+            EventColumn::Time => format_time_of_day(self.timestamp),
+            EventColumn::Delta => format_delta(self.delta),
+            EventColumn::Category => self.category.to_string(),
+            EventColumn::Peer => self.peer.map(|p| p.to_string()).unwrap_or_default(),
+            EventColumn::Connection => self.connection_id.map(|c| c.to_string()).unwrap_or_default(),
+            EventColumn::Summary => self.summary.clone(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: EventColumn) -> Ordering {
+        match column {
+            EventColumn::Time => self.timestamp.cmp(&other.timestamp),
+            EventColumn::Delta => self.delta.cmp(&other.delta),
+            EventColumn::Category => self.category.cmp(&other.category),
+            EventColumn::Peer => self.peer.map(|p| p.to_string()).cmp(&other.peer.map(|p| p.to_string())),
+            EventColumn::Connection => self.connection_id.cmp(&other.connection_id),
+            EventColumn::Summary => self.summary.cmp(&other.summary),
+        }
+    }
+}
+
+// UTC since there is no time zone handling in the scope yet
+pub fn format_time_of_day(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn format_delta(delta: Duration) -> String {
+    match delta.as_millis() {
+        0..=999 => format!("+{}ms", delta.as_millis()),
+        1000..=59999 => format!("+{:.1}s", delta.as_secs_f32()),
+        _ => format!("+{}m{:02}s", delta.as_secs() / 60, delta.as_secs() % 60),
+    }
+}
+
+pub fn events_view() -> impl View {
+    let mut category_select = SelectView::<Option<EventCategory>>::new()
+        .popup()
+        .item("all", None);
+    for category in EventCategory::ALL {
+        category_select.add_item(category.to_string(), Some(category));
+    }
+    let filter_bar = LinearLayout::horizontal()
+        .child(TextView::new("Category: "))
+        .child(category_select.on_submit(|s: &mut Cursive, category: &Option<EventCategory>| {
+            let category = *category;
+            set_filter(s, |history| history.category = category);
+        }))
+        .child(TextView::new("  Peer: "))
+        .child(
+            EditView::new()
+                .on_edit(|s: &mut Cursive, peer: &str, _cursor: usize| {
+                    let peer = peer.trim().to_string();
+                    set_filter(s, |history| history.peer = peer);
+                })
+                .min_width(20)
+                .full_width(),
+        );
+
+    let table = TableView::<ScopeEvent, EventColumn>::new()
+        .column(EventColumn::Time, "Time (UTC)", |c| c.width(16).ordering(Ordering::Greater))
+        .column(EventColumn::Delta, "Delta", |c| c.width(10))
+        .column(EventColumn::Category, "Category", |c| c.width(14))
+        .column(EventColumn::Peer, "Peer", |c| c.width_percent(20))
+        .column(EventColumn::Connection, "Conn", |c| c.width(8))
+        .column(EventColumn::Summary, "Summary", |c| c)
+        .default_column(EventColumn::Time)
+        .on_submit(|s: &mut Cursive, _row: usize, index: usize| {
+            let event = s.call_on_name(EVENTS_TABLE, |table: &mut TableView<ScopeEvent, EventColumn>| {
+                table.borrow_item(index).cloned()
+            });
+            if let Some(Some(event)) = event {
+                dlg_event_details(s, event);
+            }
+        })
+        .with_name(EVENTS_TABLE)
+        .min_height(6)
+        .full_height();
+
+    LinearLayout::vertical().child(filter_bar).child(table)
+}
+
+pub fn new_event(s: &mut Cursive, event: ScopeEvent) {
+    let ud: &mut TheApiUserData = s.user_data().unwrap();
+    let history = &mut ud.event_history;
+    let dropped = if history.events.len() >= MAX_EVENTS {
+        history.events.pop_front().filter(|e| history.matches(e))
+    } else {
+        None
+    };
+    let shown = history.matches(&event);
+    history.events.push_back(event.clone());
+    s.call_on_name(EVENTS_TABLE, |table: &mut TableView<ScopeEvent, EventColumn>| {
+        if let Some(dropped) = dropped {
+            if let Some(index) = table.borrow_items().iter().position(|e| *e == dropped) {
+                table.remove_item(index);
+            }
+        }
+        if shown {
+            table.insert_item(event);
+        }
+    });
+}
+
+fn set_filter<F: FnOnce(&mut EventHistory)>(s: &mut Cursive, change: F) {
+    let ud: &mut TheApiUserData = s.user_data().unwrap();
+    change(&mut ud.event_history);
+    let events = ud.event_history.filtered();
+    s.call_on_name(EVENTS_TABLE, |table: &mut TableView<ScopeEvent, EventColumn>| {
+        table.set_items(events)
+    });
+}
+
+fn dlg_event_details(s: &mut Cursive, event: ScopeEvent) {
+    let details = format!(
+        "Time: {} UTC ({} after the previous event)\nCategory: {}\nPeer: {}\nConnection: {}\n\n{}\n\n{}",
+        format_time_of_day(event.timestamp),
+        format_delta(event.delta),
+        event.category,
+        event.peer.map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()),
+        event.connection_id.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string()),
+        event.summary,
+        event.raw,
+    );
+    s.add_layer(
+        Dialog::around(TextView::new(details).scrollable())
+            .title("Event")
+            .dismiss_button("Close")
+            .max_width(120),
+    );
+}
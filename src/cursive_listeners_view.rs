@@ -0,0 +1,156 @@
+// Listeners table for the cursive TUI, built on cursive_table_view.
+// Rows are ListenAddrInfo records kept up to date by UiUpdate::ListenAddrChanged.
+// Enter on a row offers to copy the address to the clipboard or save it to a file,
+// since text in the terminal interface can not be selected.
+
+use std::cmp::Ordering;
+use std::io::Write;
+
+use base64::Engine;
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, TextView};
+use cursive::Cursive;
+use cursive_table_view::{TableView, TableViewItem};
+
+use crate::listeners::{ListenAddrInfo, ListenAddrState};
+
+const LISTENERS_TABLE: &str = "listeners_table";
+const SAVE_PATH_INPUT: &str = "listen_addr_save_path";
+const DEFAULT_SAVE_PATH: &str = "listen-address.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ListenAddrColumn {
+    Address,
+    State,
+}
+
+impl TableViewItem<ListenAddrColumn> for ListenAddrInfo {
+    fn to_column(&self, column: ListenAddrColumn) -> String {
+        match column {
+            ListenAddrColumn::Address => self.address.to_string(),
+            ListenAddrColumn::State => match self.last_error {
+                Some(_) => format!("{}!", self.state),
+                None => self.state.to_string(),
+            },
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: ListenAddrColumn) -> Ordering {
+        match column {
+            ListenAddrColumn::Address => self.address.to_string().cmp(&other.address.to_string()),
+            ListenAddrColumn::State => self.state.cmp(&other.state),
+        }
+    }
+}
+
+pub fn listeners_table() -> impl View {
+    TableView::<ListenAddrInfo, ListenAddrColumn>::new()
+        .column(ListenAddrColumn::Address, "Address", |c| c)
+        .column(ListenAddrColumn::State, "State", |c| c.width(11))
+        .default_column(ListenAddrColumn::State)
+        .on_submit(|s: &mut Cursive, _row: usize, index: usize| {
+            let listen_addr = s.call_on_name(
+                LISTENERS_TABLE,
+                |table: &mut TableView<ListenAddrInfo, ListenAddrColumn>| {
+                    table.borrow_item(index).cloned()
+                },
+            );
+            if let Some(Some(listen_addr)) = listen_addr {
+                dlg_listen_addr_actions(s, listen_addr);
+            }
+        })
+        .with_name(LISTENERS_TABLE)
+        .min_height(5)
+}
+
+pub fn listen_addr_changed(s: &mut Cursive, listen_addr: ListenAddrInfo) {
+    s.call_on_name(
+        LISTENERS_TABLE,
+        |table: &mut TableView<ListenAddrInfo, ListenAddrColumn>| {
+            let existing = table.borrow_items().iter().position(|a| {
+                a.listener == listen_addr.listener && a.address == listen_addr.address
+            });
+            match existing {
+                Some(index) => {
+                    if let Some(item) = table.borrow_item_mut(index) {
+                        *item = listen_addr;
+                    }
+                    table.sort();
+                }
+                None => table.insert_item(listen_addr),
+            }
+        },
+    );
+}
+
+fn dlg_listen_addr_actions(s: &mut Cursive, listen_addr: ListenAddrInfo) {
+    let mut details = format!(
+        "{}\n\nListener: {}\nState: {}",
+        listen_addr.address, listen_addr.listener, listen_addr.state,
+    );
+    if let ListenAddrState::Closed(Some(reason)) = &listen_addr.state {
+        details.push_str(&format!("\nClosed by: {}", reason));
+    }
+    if let Some(error) = &listen_addr.last_error {
+        details.push_str(&format!("\nLast error: {}", error));
+    }
+    let address = listen_addr.address.to_string();
+    let address_for_file = address.clone();
+    s.add_layer(
+        Dialog::around(TextView::new(details))
+            .title("Listen Address")
+            .button("Copy", move |s| {
+                s.pop_layer();
+                let output = match copy_to_clipboard(&address) {
+                    Ok(()) => format!("Copied {} to the clipboard", address),
+                    Err(e) => format!("Copy to clipboard failed: {}", e),
+                };
+                show_output(s, output);
+            })
+            .button("Save to file", move |s| {
+                s.pop_layer();
+                dlg_save_listen_addr(s, address_for_file.clone());
+            })
+            .dismiss_button("Close"),
+    );
+}
+
+fn dlg_save_listen_addr(s: &mut Cursive, address: String) {
+    let save = move |s: &mut Cursive| {
+        let path = s
+            .call_on_name(SAVE_PATH_INPUT, |v: &mut EditView| v.get_content())
+            .unwrap_or_default();
+        s.pop_layer();
+        let output = match std::fs::write(path.as_str(), format!("{}\n", address)) {
+            Ok(()) => format!("Saved {} to {}", address, path),
+            Err(e) => format!("Saving to {} failed: {}", path, e),
+        };
+        show_output(s, output);
+    };
+    s.add_layer(
+        Dialog::around(
+            EditView::new()
+                .content(DEFAULT_SAVE_PATH)
+                .with_name(SAVE_PATH_INPUT)
+                .min_width(40),
+        )
+        .title("Save address to file")
+        .button("Save", save)
+        .dismiss_button("Cancel"),
+    );
+}
+
+fn show_output(s: &mut Cursive, output: String) {
+    s.call_on_name("output_view", |v: &mut TextView| {
+        v.append(format!("{}\r", output))
+    });
+}
+
+// OSC 52 asks the terminal itself to set the clipboard, so it also works over SSH.
+// Under tmux it needs `set -g set-clipboard on`.
+fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+    stdout.flush()
+}
@@ -0,0 +1,151 @@
+// Peers table for the cursive TUI, built on cursive_table_view.
+// Rows are PeerInfo records kept up to date by UiUpdate::PeerChanged and PeerGone.
+// Columns sort by clicking or selecting the header, Enter on a row opens the peer actions.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+use cursive_table_view::{TableView, TableViewItem};
+use libp2p::PeerId;
+
+use crate::cursive_tui::send_command;
+use crate::peers::{PeerInfo, PeerRole};
+use crate::ui_api::UiCommand;
+
+const PEERS_TABLE: &str = "peers_table";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeerColumn {
+    PeerId,
+    Address,
+    Role,
+    Transport,
+    Muxer,
+    Connections,
+    Age,
+    Topics,
+}
+
+impl TableViewItem<PeerColumn> for PeerInfo {
+    fn to_column(&self, column: PeerColumn) -> String {
+        match column {
+            PeerColumn::PeerId => self.peer_id.to_string(),
+            PeerColumn::Address => self.address.to_string(),
+            PeerColumn::Role => self.role.to_string(),
+            PeerColumn::Transport => self.transport.clone(),
+            PeerColumn::Muxer => self.muxer.clone(),
+            PeerColumn::Connections => self.connections.to_string(),
+            PeerColumn::Age => format_age(self.connection_age()),
+            PeerColumn::Topics => self.topics.join(" "),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: PeerColumn) -> Ordering {
+        match column {
+            PeerColumn::PeerId => self.peer_id.to_string().cmp(&other.peer_id.to_string()),
+            PeerColumn::Address => self.address.to_string().cmp(&other.address.to_string()),
+            PeerColumn::Role => self.role.cmp(&other.role),
+            PeerColumn::Transport => self.transport.cmp(&other.transport),
+            PeerColumn::Muxer => self.muxer.cmp(&other.muxer),
+            PeerColumn::Connections => self.connections.cmp(&other.connections),
+            // oldest connection has the largest age
+            PeerColumn::Age => other.connected_since.cmp(&self.connected_since),
+            PeerColumn::Topics => self.topics.len().cmp(&other.topics.len()),
+        }
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+pub fn peers_table() -> impl View {
+    TableView::<PeerInfo, PeerColumn>::new()
+        .column(PeerColumn::PeerId, "Peer ID", |c| c.width_percent(30))
+        .column(PeerColumn::Address, "Remote Address", |c| c.width_percent(25))
+        .column(PeerColumn::Role, "Role", |c| c.width(10))
+        .column(PeerColumn::Transport, "Transport", |c| c.width(11))
+        .column(PeerColumn::Muxer, "Muxer", |c| c.width(10))
+        .column(PeerColumn::Connections, "Conns", |c| c.width(9))
+        .column(PeerColumn::Age, "Age", |c| c.width(8).ordering(Ordering::Less))
+        .column(PeerColumn::Topics, "Topics", |c| c)
+        .default_column(PeerColumn::Age)
+        .on_submit(|s: &mut Cursive, _row: usize, index: usize| {
+            let peer = s.call_on_name(PEERS_TABLE, |table: &mut TableView<PeerInfo, PeerColumn>| {
+                table.borrow_item(index).cloned()
+            });
+            if let Some(Some(peer)) = peer {
+                dlg_peer_actions(s, peer);
+            }
+        })
+        .with_name(PEERS_TABLE)
+        .min_height(5)
+}
+
+pub fn peer_changed(s: &mut Cursive, peer: PeerInfo) {
+    s.call_on_name(PEERS_TABLE, |table: &mut TableView<PeerInfo, PeerColumn>| {
+        let existing = table.borrow_items().iter().position(|p| p.peer_id == peer.peer_id);
+        match existing {
+            Some(index) => {
+                if let Some(item) = table.borrow_item_mut(index) {
+                    *item = peer;
+                }
+                table.sort();
+            }
+            None => table.insert_item(peer),
+        }
+    });
+}
+
+pub fn peer_gone(s: &mut Cursive, peer_id: PeerId) {
+    s.call_on_name(PEERS_TABLE, |table: &mut TableView<PeerInfo, PeerColumn>| {
+        let existing = table.borrow_items().iter().position(|p| p.peer_id == peer_id);
+        if let Some(index) = existing {
+            table.remove_item(index);
+        }
+    });
+}
+
+// Redial only makes sense when we dialed, a listener address is the peer's outgoing port
+fn dlg_peer_actions(s: &mut Cursive, peer: PeerInfo) {
+    let links: String = peer
+        .links
+        .iter()
+        .map(|l| format!("\n  {} {} over {}, {}", l.role, l.address, l.transport, l.muxer))
+        .collect();
+    let details = format!(
+        "Peer ID: {}\nAddress: {}\nRole: {}\nTransport: {}\nMuxer: {}\nConnections: {}{}\nConnected for: {}\nTopics: {}",
+        peer.peer_id,
+        peer.address,
+        peer.role,
+        peer.transport,
+        peer.muxer,
+        peer.connections,
+        links,
+        format_age(peer.connection_age()),
+        peer.topics.join(", "),
+    );
+    let peer_id = peer.peer_id;
+    let mut dialog = Dialog::around(TextView::new(details))
+        .title("Peer")
+        .button("Disconnect", move |s| {
+            send_command(s, UiCommand::HangUp(peer_id));
+            s.pop_layer();
+        });
+    if peer.role == PeerRole::Dialer {
+        let address = peer.address.clone();
+        dialog = dialog.button("Redial", move |s| {
+            send_command(s, UiCommand::Dial(address.clone()));
+            s.pop_layer();
+        });
+    }
+    s.add_layer(dialog.dismiss_button("Close"));
+}
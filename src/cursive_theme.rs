@@ -0,0 +1,41 @@
+// Color themes of the cursive TUI.
+// The dark and light themes are cursive theme files built into the binary, --theme-file loads
+// any other file in the same format, see theme_dark.toml. /theme switches while running.
+
+use std::path::Path;
+
+use cursive::theme;
+
+use crate::{CliArguments, Theme};
+
+// The theme the TUI starts with, a theme file wins over --theme
+pub fn startup_theme(args: &CliArguments) -> Result<theme::Theme, String> {
+    match &args.theme_file {
+        Some(path) => load_theme_file(path),
+        None => Ok(builtin_theme(args.theme.clone().unwrap_or_default())),
+    }
+}
+
+pub fn builtin_theme(choice: Theme) -> theme::Theme {
+    let toml = match choice {
+        Theme::Dark => include_str!("theme_dark.toml"),
+        Theme::Light => include_str!("theme_light.toml"),
+    };
+    theme::load_toml(toml).expect("the built in themes are valid theme files")
+}
+
+pub fn load_theme_file(path: &Path) -> Result<theme::Theme, String> {
+    theme::load_theme_file(path).map_err(|e| match e {
+        theme::Error::Io(e) => format!("Can not read the theme file {}: {e}", path.display()),
+        theme::Error::Parse(e) => format!("Invalid theme file {}: {e}", path.display()),
+    })
+}
+
+// For /theme, light, dark or the path of a theme file
+pub fn theme_by_name(name: &str) -> Result<theme::Theme, String> {
+    match name {
+        "dark" => Ok(builtin_theme(Theme::Dark)),
+        "light" => Ok(builtin_theme(Theme::Light)),
+        path => load_theme_file(Path::new(path)),
+    }
+}
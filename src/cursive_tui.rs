@@ -1,84 +1,164 @@
-use std::any::type_name;
+use std::error::Error;
+
+use async_trait::async_trait;
+
 // Cursive TUI api
-use cursive;
-use cursive::direction::Orientation::{Horizontal, Vertical};
+use cursive::direction::Orientation::Horizontal;
 use cursive::traits::*;
-pub use cursive::view::{Nameable, Position, Scrollable};
+pub use cursive::view::{Nameable, Scrollable};
 pub use cursive::views::{
-    Button, Dialog, EditView, LinearLayout, Panel, ResizedView, ScrollView, TextView,
+    Dialog, EditView, LinearLayout, Panel, ResizedView, TextView,
 };
-use cursive::{crossterm, CbSink, Cursive, CursiveRunnable, align};
+use cursive::{CbSink, Cursive, align};
 use cursive::utils::span::SpannedString;
-use cursive::reexports::enumset::EnumSet;
-use cursive::theme::*;
 use cursive::theme::Style;
 
 pub type CursiveCallback = dyn FnOnce(&mut Cursive) + Send;
 // fully specify tokio::sync::mpsc
-use crate::{CliArguments, Theme};
-use libp2p::{Multiaddr, PeerId};
+use crate::CliArguments;
+use crate::frontend::{CommandSender, NodeInfo, ScopeFrontend};
+use crate::cursive_theme;
+use crate::cursive_events_view::{events_view, new_event, EventHistory};
+use crate::cursive_dial_targets_view::{
+    dial_target_changed, dial_target_removed, dial_targets_table,
+};
+use crate::cursive_listeners_view::{listen_addr_changed, listeners_table};
+use crate::cursive_peers_view::{peer_changed, peer_gone, peers_table};
+use crate::identity_store::IdentitySource;
+use crate::scope_command::{ScopeCommand, HELP_TEXT};
+use crate::ui_api::{UiCommand, UiUpdate};
+use libp2p::PeerId;
+
+// The TUI as a ScopeFrontend, it runs in its own thread and updates reach it through the CbSink
+#[derive(Default)]
+pub struct CursiveFrontend {
+    cb_sink: Option<CbSink>,
+    ui_thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[async_trait]
+impl ScopeFrontend for CursiveFrontend {
+    fn name(&self) -> &'static str {
+        "TUI"
+    }
+
+    async fn start(
+        &mut self,
+        node: &NodeInfo,
+        commands: CommandSender,
+    ) -> Result<(), Box<dyn Error>> {
+        // a bad theme file is reported before the TUI takes over the terminal
+        let theme = cursive_theme::startup_theme(&node.args)?;
+        let (cb_sync_sender, cb_sync_receiver) = tokio::sync::oneshot::channel();
+        let node = node.clone();
+        // A regular sync thread running along side of the tokio runtime.
+        self.ui_thread = Some(std::thread::spawn(move || {
+            terminal_user_interface(commands.clone(),
+                                    node.peer_id,
+                                    node.identity_source,
+                                    node.args,
+                                    theme,
+                                    cb_sync_sender);
+            // Other frontends keep the command channel open, shut down when the TUI is closed
+            let _ = commands.try_send(UiCommand::Shutdown);
+        }));
+        // get callback channel from new thread
+        let cb_sink = cb_sync_receiver.await
+            .map_err(|_| "the TUI exited before it started")?;
+        self.cb_sink = Some(cb_sink);
+        Ok(())
+    }
+
+    fn update(&self, update: UiUpdate) -> Result<(), Box<dyn Error>> {
+        let cb_sink = self.cb_sink.as_ref().ok_or("the TUI is not running")?;
+        cb_sink.send(ui_update_to_cursive_callback(update))
+            .map_err(|_| "the TUI has exited")?;
+        Ok(())
+    }
+
+    // Quit the cursive loop if it still runs and wait so the terminal is restored before exit
+    async fn stop(&mut self) {
+        if let Some(cb_sink) = self.cb_sink.take() {
+            let _ = cb_sink.send(Box::new(|s: &mut Cursive| s.quit()));
+        }
+        if let Some(ui_thread) = self.ui_thread.take() {
+            let _ = tokio::task::spawn_blocking(move || ui_thread.join()).await;
+        }
+    }
+}
 
 // Cursive  UI has 2 phases
 // In the first phase the UI is declared
 // In the second phase it is run on an event loop in a standard synchronous thread.
 // See  "More about Cursive.md for notes and considerations for p2p applications."
 
-pub fn terminal_user_interface(
-    input_sender: tokio::sync::mpsc::Sender<Box<String>>,
+fn terminal_user_interface(
+    command_sender: CommandSender,
     lib_p2p_network_id: PeerId,
+    identity_source: IdentitySource,
     command_line_opts: CliArguments,
+    theme: cursive::theme::Theme,
     cb_sync_sender: tokio::sync::oneshot::Sender<CbSink>,
 ) {
     let mut curs = cursive::default();
-    let mut cb_sink = curs.cb_sink().clone();
-    cb_sync_sender.send(cb_sink);
+    let cb_sink = curs.cb_sink().clone();
+    if cb_sync_sender.send(cb_sink).is_err() {
+        return; // the network side is already gone
+    }
 
     // Initialize Cursive TUI
     cursive::logger::init();
 
-    //dark color scheme
-    match command_line_opts.theme {
-        Some(Theme::Light) => {
-            (); //For now use defaults for light theme
-        }
-        Some(Theme::Dark) => {
-            curs.load_toml(include_str!("colors.toml")).unwrap();
-        }
-        None => {
-            curs.load_toml(include_str!("colors.toml")).unwrap();
-        }
-    }
+    curs.set_theme(theme);
 
+    // Chat goes to the first topic until /topic picks another
+    let active_topic = command_line_opts.topics.first().cloned();
     curs.set_user_data(TheApiUserData {
-        input_sender,
+        command_sender,
+        active_topic: active_topic.clone(),
+        topics: Vec::new(),
+        event_history: EventHistory::default(),
         lib_p2p_network_id,
         command_line_opts: command_line_opts.clone(),
+        attached: matches!(identity_source, IdentitySource::Attached { .. }),
     });
 
+    // keys from the [keybindings] of the settings file
+    let keybindings = &command_line_opts.keybindings;
     curs.add_global_callback(
-        cursive::event::Event::CtrlChar('d'),
+        keybindings.debug_console.event(),
         move |s: &mut Cursive| {
             s.toggle_debug_console();
         },
     );
 
-    curs.add_global_callback(
-        cursive::event::Event::CtrlChar('c'),
+    // cursive quits on Ctrl-C before any view sees it, ask first instead
+    curs.clear_global_callbacks(cursive::event::Event::CtrlChar('c'));
+    // the quit key comes before every view, the settings file only takes keys that do not type
+    curs.set_on_pre_event(
+        keybindings.quit.event(),
         dlg_on_quit,
     );
 
+    // redraw every second so connection ages keep counting
+    curs.set_fps(1);
+
 // Declare views to compose
-    let user_message_input = EditView::new()
-        .on_submit(new_user_message)
-        .with_name("user_message_input")
-        .min_width(40)
+    let user_message_input = LinearLayout::horizontal()
+        .child(TextView::new(active_topic_label(active_topic.as_deref()))
+            .with_name("active_topic_label"))
+        .child(EditView::new()
+            .on_submit(new_user_message)
+            .with_name("user_message_input")
+            .min_width(40)
+            .full_width());
+
+    // A chat panel is added here for each topic once the swarm has subscribed to it
+    let chat_panels = LinearLayout::vertical()
+        .with_name("chat_panels")
+        .min_width(20)
         .full_width();
 
-    let monolith_chat_view = scope_data_panel(
-        "monolith_chat_view",
-        "Monolith Chat",
-        "   Start of chat for this node ");
-
     let output_view = scope_data_panel(
             "output_view",
             "General Output",
@@ -87,8 +167,8 @@ pub fn terminal_user_interface(
 
 
     let instance_info_view =
-        TextView::new(format!("Peer ID: {} Command Arguments: {:?}",
-        lib_p2p_network_id, command_line_opts))
+        TextView::new(format!("Peer ID: {} Identity: {} Command Arguments: {:?}",
+        lib_p2p_network_id, identity_source, command_line_opts))
         .with_name("instance_info")
         .full_width()
         .min_height(2);
@@ -104,8 +184,20 @@ pub fn terminal_user_interface(
     //    .on_select(selected_message);
 
     // CURSIVE  TUI views
-    //let peers_view
-    //let ports_view
+    let peers_view = Panel::new(peers_table())
+        .title("Peers")
+        .title_position(align::HAlign::Left)
+        .full_width();
+    // Enter on an address copies it or saves it to a file
+    let listeners_view = Panel::new(listeners_table())
+        .title("Listening")
+        .title_position(align::HAlign::Left)
+        .full_width();
+    // --dial and /stick addresses, redialed whenever the connection is lost
+    let dial_targets_view = Panel::new(dial_targets_table())
+        .title("Dial Targets")
+        .title_position(align::HAlign::Left)
+        .full_width();
 
     // Some settings or code-derivative file that allows a view into
     // The Transport
@@ -116,9 +208,16 @@ pub fn terminal_user_interface(
     // Probably best to use it together with a wire tool so no lower than
     // protocol if even that low level.
 
-    // let peers_and_ports_layout = LinearLayout::horizontal.new()
-    //    .child(peers_view)
-    //    .child(ResizedView.with_percent_width(20).child(ports_view))
+    // Swarm events, filtered by category and peer
+    let events_panel = Panel::new(events_view())
+        .title("Events")
+        .title_position(align::HAlign::Left)
+        .full_screen();
+
+    let peers_and_ports = LinearLayout::horizontal()
+        .child(peers_view)
+        .child(listeners_view)
+        .child(dial_targets_view);
 
     // todo: add a menu? or commands? or both? Commands are better because then it's scriptable
     // todo: create a better layout. make a reactive and proportional option
@@ -126,16 +225,19 @@ pub fn terminal_user_interface(
         Panel::new(
         LinearLayout::vertical()
             .child(instance_info_view)
-            //.child(peers_and_ports)
+            .child(peers_and_ports)
             .child(user_message_input)
             //.child(user_message_history)
             .child(
                 LinearLayout::new(Horizontal)
-                    .child(monolith_chat_view)
+                    .child(chat_panels)
                     .child(output_view),
-            )).title("P2P Scope - Alpha" )
+            )
+            .child(events_panel)).title("P2P Scope - Alpha" )
     );
     curs.add_layer(scope_screen);
+    // the tables above would otherwise take the focus and typing would go nowhere
+    let _ = curs.focus_name("user_message_input");
     curs.run();
 }
 
@@ -151,69 +253,271 @@ where Q: Into<SpannedString<Style>>, S: Into<String>  {
 }
 
 
+// Chat panels are found by the name of their TextView
+fn chat_view_name(topic: &str) -> String {
+    format!("chat_view:{}", topic)
+}
+
+fn chat_panel(topic: &str) -> impl View {
+    scope_data_panel(
+        chat_view_name(topic),
+        format!("#{}", topic),
+        format!("   Start of {} chat for this node \r", topic))
+}
+
+// The answer to chat and /unsubscribe while there is no chat topic
+const NO_TOPIC: &str = "No topic subscribed, use /subscribe <topic>";
+
+fn active_topic_label(topic: Option<&str>) -> String {
+    match topic {
+        Some(topic) => format!("#{} > ", topic),
+        None => "no topic > ".to_string(),
+    }
+}
+
+fn set_active_topic(s: &mut Cursive, topic: Option<String>) {
+    s.call_on_name("active_topic_label", |v: &mut TextView| {
+        v.set_content(active_topic_label(topic.as_deref()))
+    });
+    let ud: &mut TheApiUserData = s.user_data().unwrap();
+    ud.active_topic = topic;
+}
+
 // callbacks to customize the ui during declarative cursive phase to prepare the running phase
 fn new_user_message(s: &mut Cursive, message: &str) {
-    s.call_on_name("monolith_chat_view", |v: &mut TextView| {
+    if ScopeCommand::is_command(message) {
+        new_scope_command(s, message);
+        return;
+    }
+    let ud: &TheApiUserData = s.user_data().unwrap();
+    let Some(topic) = ud.active_topic.clone() else {
+        s.call_on_name("user_message_input", |v: &mut EditView| v.set_content(""));
+        s.call_on_name("output_view", |v: &mut TextView| v.append(format!("{}\r", NO_TOPIC)));
+        return;
+    };
+    let publish = UiCommand::Publish {
+        topic: topic.clone(),
+        message: message.to_string(),
+    };
+    if !send_command(s, publish) {
+        return;
+    }
+    s.call_on_name(&chat_view_name(&topic), |v: &mut TextView| {
         v.append(format!("{}\r", message))
     });
     s.call_on_name("user_message_input", |v: &mut EditView| v.set_content(""));
-    let ud: &TheApiUserData = s.user_data().unwrap();
-    ud.input_sender
-        .blocking_send(Box::new(message.to_string())).unwrap();
     //TODO: add messages to history
-    //TODO send as an event not boxed stringk
-    //TODO internal command parsing
+}
+
+// Slash commands are echoed to the output view, the result follows once the swarm has run it.
+fn new_scope_command(s: &mut Cursive, input: &str) {
+    s.call_on_name("user_message_input", |v: &mut EditView| v.set_content(""));
+    let ud: &mut TheApiUserData = s.user_data().unwrap();
+    let output = match input.parse::<ScopeCommand>() {
+        Ok(ScopeCommand::Help) => HELP_TEXT.to_string(),
+        Ok(ScopeCommand::Topic(topic)) => {
+            if ud.topics.contains(&topic) {
+                set_active_topic(s, Some(topic.clone()));
+                format!("Chat messages now go to {}", topic)
+            } else {
+                format!("Not subscribed to {}, /subscribe {} first", topic, topic)
+            }
+        }
+        Ok(ScopeCommand::Theme(name)) => match cursive_theme::theme_by_name(&name) {
+            Ok(theme) => {
+                s.set_theme(theme);
+                format!("Switched to the {} theme", name)
+            }
+            Err(e) => e,
+        },
+        Ok(ScopeCommand::Unsubscribe(None)) if ud.active_topic.is_none() => NO_TOPIC.to_string(),
+        Ok(command) => {
+            if let Some(ui_command) = command.into_ui_command(ud.active_topic.as_deref()) {
+                send_command(s, ui_command);
+            }
+            format!("> {}", input.trim())
+        }
+        Err(e) => e,
+    };
+    s.call_on_name("output_view", |v: &mut TextView| {
+        v.append(format!("{}\r", output))
+    });
+}
+
+fn add_topic(s: &mut Cursive, topic: String) {
+    s.call_on_name("chat_panels", |layout: &mut LinearLayout| {
+        if layout.find_child_from_name(&chat_view_name(&topic)).is_none() {
+            layout.add_child(chat_panel(&topic));
+        }
+    });
+    let ud: &mut TheApiUserData = s.user_data().unwrap();
+    if !ud.topics.contains(&topic) {
+        ud.topics.push(topic.clone());
+    }
+    if ud.active_topic.is_none() {
+        set_active_topic(s, Some(topic.clone()));
+    }
+    s.call_on_name("output_view", |view: &mut TextView| {
+        view.append(format!("Subscribed to {}\r", topic));
+    });
+}
+
+// When the chat topic is left chat moves on to the next subscribed topic, if there is one
+fn remove_topic(s: &mut Cursive, topic: String) {
+    s.call_on_name("chat_panels", |layout: &mut LinearLayout| {
+        if let Some(i) = layout.find_child_from_name(&chat_view_name(&topic)) {
+            layout.remove_child(i);
+        }
+    });
+    let ud: &mut TheApiUserData = s.user_data().unwrap();
+    ud.topics.retain(|t| *t != topic);
+    if ud.active_topic.as_ref() == Some(&topic) {
+        let next_topic = ud.topics.first().cloned();
+        set_active_topic(s, next_topic);
+    }
+    s.call_on_name("output_view", |view: &mut TextView| {
+        view.append(format!("Unsubscribed from {}\r", topic));
+    });
 }
 
 // CURSIVE TUI Functions
+// Hands a command to the network loop, false when it is gone and the command was reported
+pub(crate) fn send_command(s: &mut Cursive, command: UiCommand) -> bool {
+    let ud: &TheApiUserData = s.user_data().unwrap();
+    if ud.command_sender.blocking_send(command).is_ok() {
+        return true;
+    }
+    ui_update_to_cursive_callback(UiUpdate::CommandError(
+        "The network loop is gone, the command was not sent".to_string()))(s);
+    false
+}
+
+// Quitting shuts the node down, the TUI closes when the network loop is done.
+// Ctrl-C while the dialog is open quits the TUI at once.
 fn dlg_on_quit(s: &mut Cursive) {
+    if s.find_name::<Dialog>("quit_dialog").is_some() {
+        s.quit();
+        return;
+    }
+    let ud: &TheApiUserData = s.user_data().unwrap();
+    let text = match &ud.command_line_opts.goodbye {
+        _ if ud.attached => "Confirm quit?\nThe daemon keeps running.".to_string(),
+        Some(goodbye) => format!("Confirm quit?\nSays \"{}\" to every topic first.", goodbye),
+        None => "Confirm quit?".to_string(),
+    };
     s.add_layer(
-        Dialog::around(TextView::new("Confirm quit?"))
+        Dialog::around(TextView::new(text))
             .title("Quit P2P Scope?")
             .button("Cancel", |s| {
                 s.pop_layer();
-            }) //TOTO:  message ATTENTION:I QUIT to monolith chat and shut down libp2p
+            })
             .button("Confirm Quit", |s| {
-                s.quit();
-            }),
+                let ud: &TheApiUserData = s.user_data().unwrap();
+                if ud.command_sender.blocking_send(UiCommand::Shutdown).is_err() {
+                    // the network loop is already gone
+                    s.quit();
+                    return;
+                }
+                let (text, title) = if ud.attached {
+                    ("Detaching, the daemon keeps running...", "Detaching")
+                } else {
+                    ("Closing listeners and connections...", "Shutting down")
+                };
+                s.pop_layer();
+                s.add_layer(
+                    Dialog::around(TextView::new(text))
+                        .title(title)
+                        .with_name("quit_dialog"),
+                );
+            })
+            .with_name("quit_dialog"),
     );
 }
 
-pub fn ui_update_to_cursive_callback(ui_update: UiUpdate) -> Box<CursiveCallback> {
+fn ui_update_to_cursive_callback(ui_update: UiUpdate) -> Box<CursiveCallback> {
     match ui_update {
-        UiUpdate::TextMessage(topic, peer_id, message) => {
-            if topic == String::from("monolith") {
-                Box::new(move |s: &mut Cursive| {
-                    s.call_on_name("monolith_chat_view", |view: &mut TextView| {
-                        view.append(format!("ⅈ{:?}ⅈSENT\r    {}\r", peer_id, message));
-                    })
-                    .unwrap()
-                })
-            } else {
-                let out_message = format!(
-                    "Update Unimplemented: ❝{:?}❞\r",
-                    UiUpdate::TextMessage(topic, peer_id, message)
-                );
-                Box::new(move |s: &mut Cursive| {
-                    s.call_on_name("output_view", |view: &mut TextView| {
-                        view.append(out_message);
-                    })
-                    .unwrap()
+        UiUpdate::TextMessage(topic, peer_id, message) => Box::new(move |s: &mut Cursive| {
+            let shown = s.call_on_name(&chat_view_name(&topic), |view: &mut TextView| {
+                view.append(format!("ⅈ{:?}ⅈSENT\r    {}\r", peer_id, message));
+            });
+            if shown.is_none() {
+                // no panel, the topic was left while the message was on its way
+                s.call_on_name("output_view", |view: &mut TextView| {
+                    view.append(format!("#{} ⅈ{:?}ⅈSENT\r    {}\r", topic, peer_id, message));
                 })
+                .unwrap()
+            }
+        }),
+        UiUpdate::Event(event) => Box::new(move |s: &mut Cursive| new_event(s, event)),
+        UiUpdate::PeerChanged(peer) => Box::new(move |s: &mut Cursive| peer_changed(s, peer)),
+        UiUpdate::PeerGone(peer_id) => Box::new(move |s: &mut Cursive| peer_gone(s, peer_id)),
+        UiUpdate::ListenAddrChanged(listen_addr) =>
+            Box::new(move |s: &mut Cursive| listen_addr_changed(s, listen_addr)),
+        UiUpdate::DialTargetChanged(target) =>
+            Box::new(move |s: &mut Cursive| dial_target_changed(s, target)),
+        UiUpdate::DialTargetRemoved(address) =>
+            Box::new(move |s: &mut Cursive| dial_target_removed(s, address)),
+        UiUpdate::Subscribed(topic) => Box::new(move |s: &mut Cursive| add_topic(s, topic)),
+        UiUpdate::Unsubscribed(topic) => Box::new(move |s: &mut Cursive| remove_topic(s, topic)),
+        UiUpdate::TopicList(topics) => {
+            let mut out_message = format!("TOPICS: {}\r", topics.len());
+            for topic in topics {
+                out_message.push_str(&format!("  #{}\r", topic));
             }
+            Box::new(move |s: &mut Cursive| {
+                s.call_on_name("output_view", |view: &mut TextView| {
+                    view.append(out_message);
+                })
+                .unwrap()
+            })
         }
-        UiUpdate::TerminalOutput(message) => Box::new(move |s: &mut Cursive| {
+        UiUpdate::TerminalOutput(message) | UiUpdate::CommandResult(message) =>
+            Box::new(move |s: &mut Cursive| {
             s.call_on_name("output_view", |view: &mut TextView| {
                 view.append(format!("{}\r", message));
             })
             .unwrap()
         }),
+        UiUpdate::CommandError(message) => Box::new(move |s: &mut Cursive| {
+            s.call_on_name("output_view", |view: &mut TextView| {
+                // markdown drops the trailing "\r", the line ends in a separate append
+                view.append(cursive::utils::markup::markdown::parse(
+                    format!("**Error:** {}", message)));
+                view.append("\r");
+            })
+            .unwrap()
+        }),
+        UiUpdate::PeerList(peers) => {
+            let mut out_message = format!("PEERS: {}\r", peers.len());
+            for peer in peers {
+                out_message.push_str(&format!("  {}\r", peer));
+            }
+            Box::new(move |s: &mut Cursive| {
+                s.call_on_name("output_view", |view: &mut TextView| {
+                    view.append(out_message);
+                })
+                .unwrap()
+            })
+        }
+        UiUpdate::ListenerList(addrs) => {
+            let mut out_message = "LISTENERS:\r".to_string();
+            for addr in addrs {
+                out_message.push_str(&format!("  {}\r", addr));
+            }
+            Box::new(move |s: &mut Cursive| {
+                s.call_on_name("output_view", |view: &mut TextView| {
+                    view.append(out_message);
+                })
+                .unwrap()
+            })
+        }
         _ => {
             let out_message = cursive::utils::markup::markdown::parse(
-                format!("**Unimplemented!** ❝{:?}❞\r", ui_update));
+                format!("**Unimplemented!** ❝{:?}❞", ui_update));
             Box::new(move |s: &mut Cursive| {
                 s.call_on_name("output_view", |view: &mut TextView| {
                     view.append(out_message);
+                    view.append("\r");
                 })
                 .unwrap()
             })
@@ -236,31 +540,20 @@ pub fn ui_update_to_cursive_callback(ui_update: UiUpdate) -> Box<CursiveCallback
 //
 
 
-//Implementation independent UI message types
-#[derive(Debug)]
-pub enum UiUpdate {
-    // Todo: Add Times for events and times between them
-    // NewEvent(time,source,event,environment,related)
-    TextMessage(String, PeerId, String), //Topic, PeerID, Message
-    InputMessage(String),                // MessageText
-    // arbitrary program output to output_view
-    TerminalOutput(String),
-    AppendToView(ViewSpec, String),
-    ReplaceViewContent(ViewSpec, String)
-}
-
-#[derive(Debug)]
-pub enum ViewSpec {
-    ViewName(String),
-    ViewIdS(String),
-    ViewIdI(i32),
-}
-
 // cursive allows to store a user data in it's runtime so this struct is for maximizing that.
 #[derive(Debug)]
+#[allow(dead_code)]
 pub(crate) struct TheApiUserData {
-    input_sender: tokio::sync::mpsc::Sender<Box<String>>,
+    pub(crate) command_sender: CommandSender,
+    // topic chat messages are published to, None once every topic is left
+    active_topic: Option<String>,
+    // subscribed topics, each has a chat panel
+    topics: Vec<String>,
+    // every swarm event seen, the event log shows the filtered part
+    pub(crate) event_history: EventHistory,
     lib_p2p_network_id: PeerId,
     command_line_opts: CliArguments,
+    // attached to a daemon, quitting only detaches
+    attached: bool,
 }
 
@@ -0,0 +1,156 @@
+// Daemon mode and attaching the TUI to a daemon.
+// `daemon` runs the node with the control socket as its only frontend, so it outlives the
+// terminal it was started from. `attach` runs the TUI against the control socket of a daemon:
+// this loop stands in for the network loop, forwards the commands of the TUI as requests and
+// passes on the `updates` stream of the daemon. Quitting the TUI only detaches it.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+
+use libp2p::PeerId;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::control::{self, COMMAND_FAILED};
+use crate::frontend::{self, CommandSender, NodeInfo};
+use crate::identity_store::IdentitySource;
+use crate::ui_api::{UiCommand, UiUpdate};
+use crate::CliArguments;
+
+#[derive(clap::Args, Clone, Debug)]
+pub(crate) struct DaemonCommand {
+    #[arg(long, value_name = "PATH")]
+    /// Control socket to accept commands and attached TUIs on.
+    /// Defaults to <runtime dir>/p2p-scope/control.sock
+    pub(crate) socket: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub(crate) struct AttachCommand {
+    #[arg(long, value_name = "PATH")]
+    /// Control socket of the daemon. Defaults to <runtime dir>/p2p-scope/control.sock
+    socket: Option<PathBuf>,
+}
+
+// A daemon keeps running when the terminal it was started from goes away
+pub fn ignore_hangup() -> Result<(), Box<dyn Error>> {
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move { while hangups.recv().await.is_some() {} });
+    Ok(())
+}
+
+pub async fn run_attach(
+    attach: AttachCommand,
+    mut clap_args: CliArguments,
+) -> Result<(), Box<dyn Error>> {
+    let path = attach.socket.unwrap_or_else(control::default_socket_path);
+    let (reader, mut writer) = control::connect(&path).await?.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    // Ask for the node before the TUI starts so problems are printed plainly
+    control::write_line(&mut writer, &json!({"jsonrpc": "2.0", "id": 0, "method": "node"}))
+        .await?;
+    let node = loop {
+        let line = lines.next_line().await?
+            .ok_or_else(|| format!("{} closed the connection", path.display()))?;
+        let message: Value = serde_json::from_str(&line)?;
+        if message["id"] == json!(0) {
+            break message;
+        }
+    };
+    if let Some(error) = node.get("error") {
+        return Err(format!("{} did not describe its node: {}", path.display(), error["message"]).into());
+    }
+    let peer_id: PeerId = serde_json::from_value(node["result"]["peer_id"].clone())?;
+    // chat goes to the first topic of the daemon
+    clap_args.topics = serde_json::from_value(node["result"]["topics"].clone())?;
+    let identity_source = IdentitySource::Attached {
+        socket: path.clone(),
+        identity: node["result"]["identity"].as_str().unwrap_or_default().to_string(),
+    };
+    control::write_line(&mut writer, &json!({"jsonrpc": "2.0", "id": 1, "method": "updates"}))
+        .await?;
+
+    let (command_sender, mut command_receiver) = CommandSender::channel(32);
+    let node_info = NodeInfo { peer_id, identity_source, args: clap_args.clone() };
+    let mut frontends = frontend::frontends_for(&clap_args);
+    frontends.start(&node_info, command_sender).await?;
+
+    // Requests waiting for their response, with the reply channel of the frontend if it wants one
+    let mut pending = HashMap::new();
+    let mut next_id: u64 = 2;
+    let mut connected = true;
+    loop {
+        tokio::select! {
+            command = command_receiver.recv() => {
+                // the daemon keeps running
+                let Some((command, reply_sender)) =
+                    command.filter(|(c, _)| *c != UiCommand::Shutdown) else {
+                    frontends.stop().await;
+                    return Ok(());
+                };
+                let error = match control::request_for(&command) {
+                    Some(_) if !connected => format!("The daemon at {} is gone", path.display()),
+                    Some((method, params)) => {
+                        let request = json!({"jsonrpc": "2.0", "id": next_id, "method": method, "params": params});
+                        match control::write_line(&mut writer, &request).await {
+                            Ok(()) => {
+                                pending.insert(next_id, reply_sender);
+                                next_id += 1;
+                                continue;
+                            }
+                            Err(e) => format!("Sending {method} to the daemon failed: {e}"),
+                        }
+                    }
+                    None => format!("Not available when attached to a daemon: {:?}", command),
+                };
+                frontends.update(UiUpdate::CommandError(error.clone()));
+                if let Some(reply_sender) = reply_sender {
+                    let _ = reply_sender.send(Err(error));
+                }
+            }
+            line = lines.next_line(), if connected => {
+                let Ok(Some(line)) = line else {
+                    connected = false;
+                    frontends.update(UiUpdate::TerminalOutput(format!(
+                        "The daemon at {} closed the connection, Ctrl-C to quit.", path.display())));
+                    continue;
+                };
+                let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                    continue;
+                };
+                match message["method"].as_str() {
+                    Some("update") => {
+                        if let Ok(update) = serde_json::from_value(message["params"].clone()) {
+                            frontends.update(update);
+                        }
+                    }
+                    Some("lagged") => frontends.update(UiUpdate::CommandError(format!(
+                        "Missed {} updates from the daemon", message["params"]["skipped"]))),
+                    _ => {
+                        let Some(reply_sender) = message["id"].as_u64()
+                            .and_then(|id| pending.remove(&id)) else {
+                            continue;
+                        };
+                        // the reply itself comes with the updates like for any frontend of the daemon
+                        let reply = match message.get("error") {
+                            Some(error) => {
+                                let text = error["message"].as_str().unwrap_or_default().to_string();
+                                if error["code"] != json!(COMMAND_FAILED) {
+                                    frontends.update(UiUpdate::CommandError(text.clone()));
+                                }
+                                Err(text)
+                            }
+                            None => Ok(None),
+                        };
+                        if let Some(reply_sender) = reply_sender {
+                            let _ = reply_sender.send(reply);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,434 @@
+// Sticky dial targets, the addresses the node keeps connected to.
+// Addresses given with --dial or UiCommand::Stick are dialed again whenever the dial fails or
+// the connection closes. The wait before the next attempt doubles with every failed dial up to
+// --redial-max, with some jitter so nodes that lost each other do not redial in step.
+// The network loop feeds the dial and connection events in here, forwards the resulting
+// UiUpdate and calls redial_due at next_attempt. Failed dials are matched to their target by
+// ConnectionId, which the swarm only tells the behaviour, see DialFailures.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use libp2p::core::{ConnectedPoint, Endpoint};
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::swarm::{
+    dummy, ConnectionDenied, ConnectionId, DialError, FromSwarm, NetworkBehaviour,
+    PollParameters, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::behaviour::AppBehaviour;
+use crate::ui_api::UiUpdate;
+
+const DEFAULT_INITIAL_MS: u64 = 1000;
+const DEFAULT_MAX_MS: u64 = 60_000;
+const DEFAULT_JITTER_PERCENT: u8 = 20;
+// Shorter waits would redial an unreachable address in a busy loop
+const MIN_WAIT_MS: u64 = 10;
+// A day, longer waits are surely a typo and would not fit an Instant everywhere
+const MAX_WAIT_MS: u64 = 24 * 60 * 60 * 1000;
+
+// How long to wait before redialing, see --help
+#[derive(clap::Args, Clone, Debug)]
+pub struct RedialOptions {
+    #[arg(long = "redial-initial", value_name = "MILLISECONDS",
+        default_value_t = DEFAULT_INITIAL_MS,
+        value_parser = clap::value_parser!(u64).range(MIN_WAIT_MS..=MAX_WAIT_MS))]
+    /// Wait before redialing a --dial address, doubled after every failed attempt.
+    pub initial_ms: u64,
+    #[arg(long = "redial-max", value_name = "MILLISECONDS", default_value_t = DEFAULT_MAX_MS,
+        value_parser = clap::value_parser!(u64).range(..=MAX_WAIT_MS))]
+    /// Longest wait between two dials of a --dial address.
+    pub max_ms: u64,
+    #[arg(long = "redial-jitter", value_name = "PERCENT",
+        default_value_t = DEFAULT_JITTER_PERCENT, value_parser = clap::value_parser!(u8).range(0..=100))]
+    /// Lengthen or shorten every wait by a random amount of up to PERCENT.
+    pub jitter_percent: u8,
+}
+
+impl Default for RedialOptions {
+    fn default() -> Self {
+        RedialOptions {
+            initial_ms: DEFAULT_INITIAL_MS,
+            max_ms: DEFAULT_MAX_MS,
+            jitter_percent: DEFAULT_JITTER_PERCENT,
+        }
+    }
+}
+
+impl RedialOptions {
+    // The ranges clap checks for the command line, for the settings file, and that the first
+    // wait is not longer than the longest, which clap can not check
+    pub fn check(&self) -> Result<(), String> {
+        if self.initial_ms < MIN_WAIT_MS {
+            return Err(format!("redial initial wait must be at least {MIN_WAIT_MS} milliseconds"));
+        }
+        if self.initial_ms > MAX_WAIT_MS || self.max_ms > MAX_WAIT_MS {
+            return Err(format!("redial waits must be at most {MAX_WAIT_MS} milliseconds"));
+        }
+        if self.initial_ms > self.max_ms {
+            return Err(format!(
+                "redial initial wait of {} milliseconds is longer than the longest wait of {}",
+                self.initial_ms, self.max_ms
+            ));
+        }
+        if self.jitter_percent > 100 {
+            return Err("redial jitter-percent must be 0 to 100".to_string());
+        }
+        Ok(())
+    }
+
+    // The wait after `failures` failed dials in a row
+    fn backoff(&self, failures: u32) -> Duration {
+        let wait = Duration::from_millis(self.initial_ms)
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(Duration::from_millis(self.max_ms));
+        let jitter = f64::from(self.jitter_percent) / 100.0;
+        wait.mul_f64(1.0 + rand::thread_rng().gen_range(-jitter..=jitter))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DialTargetState {
+    Dialing,
+    Connected,
+    // Waiting to dial again after a failed dial or a closed connection
+    BackingOff { next_attempt: SystemTime },
+}
+
+impl fmt::Display for DialTargetState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialTargetState::Dialing => write!(f, "dialing"),
+            DialTargetState::Connected => write!(f, "connected"),
+            DialTargetState::BackingOff { .. } => write!(f, "backing off"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DialTargetInfo {
+    pub address: Multiaddr,
+    // From the /p2p suffix of the address or the first connection
+    pub peer_id: Option<PeerId>,
+    pub state: DialTargetState,
+    pub since: SystemTime,
+    // Failed dials since the last connection
+    pub failures: u32,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct DialTarget {
+    info: DialTargetInfo,
+    // Set while backing off
+    redial_at: Option<Instant>,
+    // Set while dialing
+    dial: Option<ConnectionId>,
+}
+
+#[derive(Debug)]
+pub struct DialTargets {
+    options: RedialOptions,
+    targets: Vec<DialTarget>,
+}
+
+impl DialTargets {
+    pub fn new(options: RedialOptions) -> Self {
+        DialTargets {
+            options,
+            targets: Vec::new(),
+        }
+    }
+
+    // Dials right away, a dial that fails at once already backs off
+    pub fn add(
+        &mut self,
+        swarm: &mut Swarm<AppBehaviour>,
+        address: Multiaddr,
+    ) -> Result<UiUpdate, String> {
+        if self.find(&address).is_some() {
+            return Err(format!("Already keeping {address} connected"));
+        }
+        let peer_id = address.iter().find_map(|p| match p {
+            Protocol::P2p(hash) => PeerId::from_multihash(hash).ok(),
+            _ => None,
+        });
+        self.targets.push(DialTarget {
+            info: DialTargetInfo {
+                address,
+                peer_id,
+                state: DialTargetState::Dialing,
+                since: SystemTime::now(),
+                failures: 0,
+                last_error: None,
+            },
+            redial_at: None,
+            dial: None,
+        });
+        Ok(self.dial(self.targets.len() - 1, swarm))
+    }
+
+    // Open connections to the address stay open
+    pub fn remove(&mut self, address: &Multiaddr) -> Result<UiUpdate, String> {
+        let index = self
+            .find(address)
+            .ok_or_else(|| format!("Not keeping {address} connected"))?;
+        let target = self.targets.remove(index);
+        Ok(UiUpdate::DialTargetRemoved(target.info.address))
+    }
+
+    pub fn next_attempt(&self) -> Option<Instant> {
+        self.targets.iter().filter_map(|t| t.redial_at).min()
+    }
+
+    pub fn redial_due(&mut self, swarm: &mut Swarm<AppBehaviour>) -> Vec<UiUpdate> {
+        let now = Instant::now();
+        let due: Vec<usize> = (0..self.targets.len())
+            .filter(|&index| self.targets[index].redial_at.is_some_and(|at| at <= now))
+            .collect();
+        due.into_iter().map(|index| self.dial(index, swarm)).collect()
+    }
+
+    // A target also counts as connected when its peer dialed us
+    pub fn connection_established(
+        &mut self,
+        peer_id: PeerId,
+        endpoint: &ConnectedPoint,
+    ) -> Vec<UiUpdate> {
+        let dialed = match endpoint {
+            ConnectedPoint::Dialer { address, .. } => Some(without_p2p(address)),
+            ConnectedPoint::Listener { .. } => None,
+        };
+        let indices: Vec<usize> = (0..self.targets.len())
+            .filter(|&index| {
+                let info = &self.targets[index].info;
+                info.state != DialTargetState::Connected
+                    && (info.peer_id == Some(peer_id)
+                        || dialed.as_ref() == Some(&without_p2p(&info.address)))
+            })
+            .collect();
+        indices
+            .into_iter()
+            .map(|index| {
+                let target = &mut self.targets[index];
+                target.redial_at = None;
+                target.dial = None;
+                target.info.peer_id = Some(peer_id);
+                target.info.failures = 0;
+                target.info.state = DialTargetState::Connected;
+                target.info.since = SystemTime::now();
+                UiUpdate::DialTargetChanged(target.info.clone())
+            })
+            .collect()
+    }
+
+    // Only the last connection to the peer closing sends a target back to backing off
+    pub fn connection_closed(
+        &mut self,
+        peer_id: PeerId,
+        num_established: u32,
+        cause: Option<String>,
+    ) -> Vec<UiUpdate> {
+        if num_established > 0 {
+            return Vec::new();
+        }
+        let indices: Vec<usize> = (0..self.targets.len())
+            .filter(|&index| {
+                let info = &self.targets[index].info;
+                info.state == DialTargetState::Connected && info.peer_id == Some(peer_id)
+            })
+            .collect();
+        let reason = cause.unwrap_or_else(|| "Connection closed".to_string());
+        indices
+            .into_iter()
+            .map(|index| self.back_off(index, reason.clone()))
+            .collect()
+    }
+
+    // A failed dial without a ConnectionId was not made by a target
+    pub fn dial_failed(
+        &mut self,
+        connection_id: Option<ConnectionId>,
+        error: &DialError,
+    ) -> Vec<UiUpdate> {
+        let Some(connection_id) = connection_id else {
+            return Vec::new();
+        };
+        let indices: Vec<usize> = (0..self.targets.len())
+            .filter(|&index| self.targets[index].dial == Some(connection_id))
+            .collect();
+        indices
+            .into_iter()
+            .map(|index| {
+                self.targets[index].dial = None;
+                self.targets[index].info.failures += 1;
+                self.back_off(index, error.to_string())
+            })
+            .collect()
+    }
+
+    fn dial(&mut self, index: usize, swarm: &mut Swarm<AppBehaviour>) -> UiUpdate {
+        let target = &mut self.targets[index];
+        target.redial_at = None;
+        let opts = DialOpts::from(target.info.address.clone());
+        let connection_id = opts.connection_id();
+        match swarm.dial(opts) {
+            Ok(()) => {
+                target.dial = Some(connection_id);
+                target.info.state = DialTargetState::Dialing;
+                target.info.since = SystemTime::now();
+                UiUpdate::DialTargetChanged(target.info.clone())
+            }
+            Err(e) => {
+                target.info.failures += 1;
+                self.back_off(index, e.to_string())
+            }
+        }
+    }
+
+    fn back_off(&mut self, index: usize, reason: String) -> UiUpdate {
+        let target = &mut self.targets[index];
+        let wait = self.options.backoff(target.info.failures.saturating_sub(1));
+        let now = Instant::now();
+        target.redial_at = Some(now.checked_add(wait).unwrap_or(now));
+        target.info.state = DialTargetState::BackingOff {
+            next_attempt: SystemTime::now().checked_add(wait).unwrap_or_else(SystemTime::now),
+        };
+        target.info.since = SystemTime::now();
+        target.info.last_error = Some(reason);
+        UiUpdate::DialTargetChanged(target.info.clone())
+    }
+
+    fn find(&self, address: &Multiaddr) -> Option<usize> {
+        let address = without_p2p(address);
+        self.targets
+            .iter()
+            .position(|t| without_p2p(&t.info.address) == address)
+    }
+}
+
+// The swarm reports dialed addresses with or without the /p2p suffix
+fn without_p2p(address: &Multiaddr) -> Multiaddr {
+    address
+        .iter()
+        .filter(|p| !matches!(p, Protocol::P2p(_)))
+        .collect()
+}
+
+// Remembers the ConnectionId of the last failed dial for the OutgoingConnectionError that
+// follows it, the swarm event itself does not carry the id. Every failure overwrites it, so
+// what is left from dials that failed right away does not matter.
+#[derive(Debug, Default)]
+pub struct DialFailures {
+    last: Option<ConnectionId>,
+}
+
+impl DialFailures {
+    pub fn take(&mut self) -> Option<ConnectionId> {
+        self.last.take()
+    }
+}
+
+impl NetworkBehaviour for DialFailures {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type OutEvent = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        if let FromSwarm::DialFailure(failure) = event {
+            self.last = Some(failure.connection_id);
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(initial_ms: u64, max_ms: u64, jitter_percent: u8) -> RedialOptions {
+        RedialOptions { initial_ms, max_ms, jitter_percent }
+    }
+
+    #[test]
+    fn backoff_doubles_without_jitter() {
+        let options = options(100, 60_000, 0);
+        let waits: Vec<u64> = (0..5).map(|n| options.backoff(n).as_millis() as u64).collect();
+        assert_eq!(waits, [100, 200, 400, 800, 1600]);
+    }
+
+    #[test]
+    fn backoff_saturates_at_max() {
+        let options = options(1000, 60_000, 0);
+        assert_eq!(options.backoff(6), Duration::from_millis(60_000));
+        assert_eq!(options.backoff(40), Duration::from_millis(60_000));
+        assert_eq!(options.backoff(u32::MAX), Duration::from_millis(60_000));
+        let longest = RedialOptions { initial_ms: MAX_WAIT_MS, max_ms: MAX_WAIT_MS, ..options };
+        assert_eq!(longest.backoff(u32::MAX), Duration::from_millis(MAX_WAIT_MS));
+    }
+
+    #[test]
+    fn backoff_jitter_stays_in_bounds() {
+        let steady = options(1000, 60_000, 0);
+        let jittery = options(1000, 60_000, 20);
+        for failures in [0, 3, 10] {
+            let wait = steady.backoff(failures);
+            for _ in 0..1000 {
+                let jittered = jittery.backoff(failures);
+                assert!(jittered >= wait.mul_f64(0.8) && jittered <= wait.mul_f64(1.2));
+            }
+        }
+    }
+
+    #[test]
+    fn check_rejects_out_of_range_waits() {
+        assert!(RedialOptions::default().check().is_ok());
+        assert!(options(10, 10, 100).check().is_ok());
+        assert!(options(9, 1000, 20).check().is_err());
+        assert!(options(2000, 1000, 20).check().is_err());
+        assert!(options(1000, MAX_WAIT_MS + 1, 20).check().is_err());
+        assert!(options(1000, 60_000, 101).check().is_err());
+    }
+}
@@ -0,0 +1,261 @@
+// DNS for dialing /dns, /dns4, /dns6 and /dnsaddr addresses.
+// The names are resolved by the transport of libp2p-dns. Dns puts two things around it: a dial
+// of an address the wrapped transport can not take is refused right away, as libp2p-dns would
+// only fail it once resolved, so an OrTransport of two of them still falls through to the
+// second one. And the outcome of every dial of a name is sent to the network loop for the
+// event log.
+
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use libp2p::core::transport::{ListenerId, TransportError, TransportEvent};
+use libp2p::dns::{
+    DnsErr, ResolveError, ResolveErrorKind, ResolverConfig, ResolverOpts, TokioDnsConfig,
+};
+use libp2p::futures::future::{BoxFuture, Either};
+use libp2p::futures::FutureExt;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, Transport};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use trust_dns_resolver::config::NameServerConfigGroup;
+use trust_dns_resolver::system_conf;
+
+const DNS_PORT: u16 = 53;
+
+// Where names are resolved, see --dns-resolver
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DnsResolver {
+    // /etc/resolv.conf or the platform's equivalent
+    #[default]
+    System,
+    Cloudflare,
+    Google,
+    Quad9,
+    // A name server of your own, plain DNS over UDP and TCP
+    Server(SocketAddr),
+}
+
+impl DnsResolver {
+    fn config(&self) -> Result<(ResolverConfig, ResolverOpts), Box<dyn Error>> {
+        Ok(match self {
+            DnsResolver::System => system_conf::read_system_conf()
+                .map_err(|e| format!("Can not read the system DNS configuration: {e}"))?,
+            DnsResolver::Cloudflare => (ResolverConfig::cloudflare(), ResolverOpts::default()),
+            DnsResolver::Google => (ResolverConfig::google(), ResolverOpts::default()),
+            DnsResolver::Quad9 => (ResolverConfig::quad9(), ResolverOpts::default()),
+            DnsResolver::Server(server) => {
+                let servers =
+                    NameServerConfigGroup::from_ips_clear(&[server.ip()], server.port(), true);
+                (ResolverConfig::from_parts(None, Vec::new(), servers), ResolverOpts::default())
+            }
+        })
+    }
+}
+
+impl FromStr for DnsResolver {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "system" => Ok(DnsResolver::System),
+            "cloudflare" => Ok(DnsResolver::Cloudflare),
+            "google" => Ok(DnsResolver::Google),
+            "quad9" => Ok(DnsResolver::Quad9),
+            server => server
+                .parse::<SocketAddr>()
+                .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DNS_PORT)))
+                .map(DnsResolver::Server)
+                .map_err(|_| {
+                    format!(
+                        "Unknown resolver {server}, use system, cloudflare, google, quad9 \
+                         or the address of a name server like 192.0.2.53:53"
+                    )
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for DnsResolver {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<DnsResolver> for String {
+    fn from(resolver: DnsResolver) -> Self {
+        resolver.to_string()
+    }
+}
+
+impl fmt::Display for DnsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsResolver::System => write!(f, "system"),
+            DnsResolver::Cloudflare => write!(f, "cloudflare"),
+            DnsResolver::Google => write!(f, "google"),
+            DnsResolver::Quad9 => write!(f, "quad9"),
+            DnsResolver::Server(server) => write!(f, "{server}"),
+        }
+    }
+}
+
+// The resolver configuration of all Dns layers and where they report their lookups
+#[derive(Clone)]
+pub struct DnsLayers {
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    summaries: mpsc::UnboundedSender<String>,
+}
+
+impl DnsLayers {
+    pub fn new(
+        resolver: &DnsResolver,
+        summaries: mpsc::UnboundedSender<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (config, opts) = resolver.config()?;
+        Ok(DnsLayers { config, opts, summaries })
+    }
+
+    // Resolves the DNS names of addresses `accepts` says the transport can dial
+    pub fn wrap<T>(
+        &self,
+        inner: T,
+        accepts: fn(&Multiaddr) -> bool,
+    ) -> Result<Dns<T>, Box<dyn Error>>
+    where
+        T: Transport + Send + Unpin + 'static,
+        T::Error: Send,
+        T::Dial: Send,
+    {
+        let inner = TokioDnsConfig::custom(inner, self.config.clone(), self.opts)
+            .map_err(|e| format!("Starting the DNS resolver failed: {e}"))?;
+        Ok(Dns { inner, accepts, summaries: self.summaries.clone() })
+    }
+}
+
+// The resolver puts the whole query into its errors, a missing name is the common case
+fn lookup_error(e: &ResolveError) -> String {
+    match e.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => "no such name".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+fn has_dns_name(address: &Multiaddr) -> bool {
+    address.iter().any(|protocol| {
+        matches!(
+            protocol,
+            Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_)
+        )
+    })
+}
+
+fn summary<O, E: fmt::Display>(address: &Multiaddr, result: &Result<O, DnsErr<E>>) -> String {
+    match result {
+        Ok(_) => format!("{address}: resolved and connected"),
+        Err(DnsErr::ResolveError(e)) => format!("{address}: resolving failed, {}", lookup_error(e)),
+        Err(DnsErr::TooManyLookups) => format!("{address}: resolving failed, too many lookups"),
+        Err(DnsErr::MultiaddrNotSupported(resolved)) => {
+            format!("{address}: resolved to {resolved}, which the transport can not dial")
+        }
+        Err(DnsErr::Transport(e)) => format!("{address}: resolved, dialing failed: {e}"),
+    }
+}
+
+// A libp2p-dns transport that refuses addresses its transport can not dial and reports the
+// dials of DNS names
+pub struct Dns<T> {
+    inner: TokioDnsConfig<T>,
+    accepts: fn(&Multiaddr) -> bool,
+    summaries: mpsc::UnboundedSender<String>,
+}
+
+impl<T> Dns<T>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: Send,
+    T::Error: fmt::Display + Send,
+    T::Dial: Send,
+{
+    fn logged(
+        &self,
+        address: Multiaddr,
+        dial: Result<<TokioDnsConfig<T> as Transport>::Dial, TransportError<DnsErr<T::Error>>>,
+    ) -> Result<<Self as Transport>::Dial, TransportError<DnsErr<T::Error>>> {
+        let dial = dial?;
+        if !has_dns_name(&address) {
+            return Ok(Either::Left(dial));
+        }
+        let summaries = self.summaries.clone();
+        Ok(Either::Right(
+            async move {
+                let result = dial.await;
+                let _ = summaries.send(summary(&address, &result));
+                result
+            }
+            .boxed(),
+        ))
+    }
+}
+
+impl<T> Transport for Dns<T>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: Send,
+    T::Error: fmt::Display + Send,
+    T::Dial: Send,
+{
+    type Output = T::Output;
+    type Error = DnsErr<T::Error>;
+    type ListenerUpgrade = <TokioDnsConfig<T> as Transport>::ListenerUpgrade;
+    type Dial = Either<
+        <TokioDnsConfig<T> as Transport>::Dial,
+        BoxFuture<'static, Result<Self::Output, Self::Error>>,
+    >;
+
+    fn listen_on(&mut self, addr: Multiaddr) -> Result<ListenerId, TransportError<Self::Error>> {
+        self.inner.listen_on(addr)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.inner.remove_listener(id)
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if !(self.accepts)(&addr) {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        }
+        let dial = self.inner.dial(addr.clone());
+        self.logged(addr, dial)
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if !(self.accepts)(&addr) {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        }
+        let dial = self.inner.dial_as_listener(addr.clone());
+        self.logged(addr, dial)
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
@@ -0,0 +1,177 @@
+// Frontends of the scope and the fan-out that lets several run at once.
+// A frontend shows the UiUpdates of the network loop and sends UiCommands back, see ui_api.rs.
+// The cursive TUI, the headless JSON Lines frontend, the control socket and the web dashboard
+// implement ScopeFrontend, the network loop only talks to a FrontendFanout and does not know
+// which frontends are running.
+
+use std::cell::RefCell;
+use std::error::Error;
+
+use async_trait::async_trait;
+use libp2p::PeerId;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::{mpsc, oneshot};
+
+#[cfg(unix)]
+use crate::control::{self, ControlFrontend};
+use crate::cursive_tui::CursiveFrontend;
+use crate::headless::HeadlessFrontend;
+use crate::identity_store::IdentitySource;
+use crate::ui_api::{UiCommand, UiUpdate};
+#[cfg(feature = "web")]
+use crate::web::WebFrontend;
+use crate::{CliArguments, CliCommand};
+
+// What a frontend needs to know about the node it shows
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    pub peer_id: PeerId,
+    pub identity_source: IdentitySource,
+    pub args: CliArguments,
+}
+
+#[async_trait]
+pub trait ScopeFrontend: Send {
+    // Short name used when reporting problems with the frontend
+    fn name(&self) -> &'static str;
+
+    // Called once before any update. The frontend keeps `commands` for as long as it wants to
+    // drive the node, the node shuts down when every frontend has dropped its sender.
+    async fn start(
+        &mut self,
+        node: &NodeInfo,
+        commands: CommandSender,
+    ) -> Result<(), Box<dyn Error>>;
+
+    // Show an update. An error means the frontend is gone and it is not called again.
+    fn update(&self, update: UiUpdate) -> Result<(), Box<dyn Error>>;
+
+    // Called once when the node shuts down, after the last update
+    async fn stop(&mut self) {}
+}
+
+// The outcome of a command for the frontend that asked with CommandSender::request.
+// Every frontend also gets the reply as a UiUpdate, Ok(None) when there is none.
+pub type CommandReply = Result<Option<UiUpdate>, String>;
+
+// What the network loop receives, the reply channel is only there for requests
+pub type CommandRequest = (UiCommand, Option<oneshot::Sender<CommandReply>>);
+
+// The sending half of the command channel of the network loop
+#[derive(Debug, Clone)]
+pub struct CommandSender(mpsc::Sender<CommandRequest>);
+
+impl CommandSender {
+    pub fn channel(buffer: usize) -> (CommandSender, mpsc::Receiver<CommandRequest>) {
+        let (sender, receiver) = mpsc::channel(buffer);
+        (CommandSender(sender), receiver)
+    }
+
+    pub async fn send(&self, command: UiCommand) -> Result<(), SendError<UiCommand>> {
+        self.0
+            .send((command, None))
+            .await
+            .map_err(|SendError((command, _))| SendError(command))
+    }
+
+    // For the cursive callbacks, which run outside of tokio
+    pub fn blocking_send(&self, command: UiCommand) -> Result<(), SendError<UiCommand>> {
+        self.0
+            .blocking_send((command, None))
+            .map_err(|SendError((command, _))| SendError(command))
+    }
+
+    // For when the network loop may not be reading, e.g. while it waits for a frontend to stop
+    pub fn try_send(&self, command: UiCommand) -> Result<(), TrySendError<UiCommand>> {
+        self.0.try_send((command, None)).map_err(|e| match e {
+            TrySendError::Full((command, _)) => TrySendError::Full(command),
+            TrySendError::Closed((command, _)) => TrySendError::Closed(command),
+        })
+    }
+
+    // Send a command and wait for its reply
+    pub async fn request(&self, command: UiCommand) -> CommandReply {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.0
+            .send((command, Some(reply_sender)))
+            .await
+            .map_err(|_| "The node is shutting down".to_string())?;
+        reply_receiver
+            .await
+            .map_err(|_| "The node is shutting down".to_string())?
+    }
+}
+
+// The frontends picked on the command line
+pub fn frontends_for(args: &CliArguments) -> FrontendFanout {
+    let mut frontends = FrontendFanout::default();
+    if matches!(args.command, Some(CliCommand::Daemon(_))) {
+        // only the control socket
+    } else if args.headless {
+        frontends.add(Box::new(HeadlessFrontend::new(args.exit_on_eof)));
+    } else {
+        frontends.add(Box::<CursiveFrontend>::default());
+    }
+    #[cfg(unix)]
+    if let Some(path) = control::control_socket(args) {
+        frontends.add(Box::new(ControlFrontend::new(path)));
+    }
+    #[cfg(feature = "web")]
+    if let Some(address) = args.web {
+        frontends.add(Box::new(WebFrontend::new(address)));
+    }
+    frontends
+}
+
+// Hands every update to each frontend.
+// The frontends are in a RefCell so updates only need a shared reference, the network loop
+// sends them from several closures.
+#[derive(Default)]
+pub struct FrontendFanout {
+    frontends: RefCell<Vec<Box<dyn ScopeFrontend>>>,
+}
+
+impl FrontendFanout {
+    pub fn add(&mut self, frontend: Box<dyn ScopeFrontend>) {
+        self.frontends.get_mut().push(frontend);
+    }
+
+    pub async fn start(
+        &mut self,
+        node: &NodeInfo,
+        commands: CommandSender,
+    ) -> Result<(), Box<dyn Error>> {
+        for frontend in self.frontends.get_mut().iter_mut() {
+            frontend
+                .start(node, commands.clone())
+                .await
+                .map_err(|e| format!("Starting the {} frontend failed: {e}", frontend.name()))?;
+        }
+        Ok(())
+    }
+
+    // A frontend that fails is dropped and the others are told about it
+    pub fn update(&self, update: UiUpdate) {
+        let mut frontends = self.frontends.borrow_mut();
+        let mut failures = Vec::new();
+        frontends.retain(|frontend| match frontend.update(update.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+                failures.push(format!("The {} frontend stopped: {e}", frontend.name()));
+                false
+            }
+        });
+        for message in failures {
+            for frontend in frontends.iter() {
+                let _ = frontend.update(UiUpdate::CommandError(message.clone()));
+            }
+        }
+    }
+
+    pub async fn stop(&self) {
+        let frontends = self.frontends.take();
+        for mut frontend in frontends {
+            frontend.stop().await;
+        }
+    }
+}
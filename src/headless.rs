@@ -0,0 +1,89 @@
+// Headless frontend for running the scope without a terminal, e.g. in CI or test harnesses.
+// UiUpdates are written to stdout as JSON Lines and UiCommands are read from stdin,
+// one JSON value per line in the serde format of ui_api.rs, for example
+//   {"Dial":"/ip4/127.0.0.1/tcp/4001"}
+//   {"Publish":{"topic":"monolith","message":"hello"}}
+//   {"Query":"Peers"}
+//   "Shutdown"
+// The node keeps running at the end of stdin, it stops on a Shutdown command, Ctrl-C or
+// SIGTERM. With --exit-on-eof the end of stdin shuts it down too.
+
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::thread;
+
+use async_trait::async_trait;
+use crate::frontend::{CommandSender, NodeInfo, ScopeFrontend};
+use crate::ui_api::{UiCommand, UiUpdate};
+
+#[derive(Debug, Default)]
+pub struct HeadlessFrontend {
+    exit_on_eof: bool,
+}
+
+impl HeadlessFrontend {
+    pub fn new(exit_on_eof: bool) -> Self {
+        HeadlessFrontend { exit_on_eof }
+    }
+}
+
+#[async_trait]
+impl ScopeFrontend for HeadlessFrontend {
+    fn name(&self) -> &'static str {
+        "headless"
+    }
+
+    async fn start(
+        &mut self,
+        node: &NodeInfo,
+        commands: CommandSender,
+    ) -> Result<(), Box<dyn Error>> {
+        spawn_command_reader(commands, self.exit_on_eof)?;
+        // the TUI shows this in its instance info
+        write_update(&UiUpdate::TerminalOutput(format!(
+            "Peer ID: {} Identity: {}",
+            node.peer_id, node.identity_source
+        )))?;
+        Ok(())
+    }
+
+    fn update(&self, update: UiUpdate) -> Result<(), Box<dyn Error>> {
+        Ok(write_update(&update)?)
+    }
+}
+
+fn write_update(update: &UiUpdate) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, update)?;
+    stdout.write_all(b"\n")?;
+    stdout.flush()
+}
+
+// Lines that are not a command are answered with a CommandError on stdout.
+// A thread of its own reads stdin, a read blocking in the runtime would keep the process
+// from exiting after a shutdown while stdin is still open.
+fn spawn_command_reader(command_sender: CommandSender, exit_on_eof: bool) -> io::Result<()> {
+    thread::Builder::new().name("headless stdin".to_string()).spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<UiCommand>(&line) {
+                Ok(command) => {
+                    if command_sender.blocking_send(command).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    let _ = write_update(&UiUpdate::CommandError(
+                        format!("Invalid command {line}: {e}")));
+                }
+            }
+        }
+        if exit_on_eof {
+            let _ = command_sender.blocking_send(UiCommand::Shutdown);
+        }
+    })?;
+    Ok(())
+}
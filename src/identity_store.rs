@@ -0,0 +1,286 @@
+// Node identity persistence.
+// A libp2p PeerId is derived from the public half of the node keypair, so generating a new
+// keypair on every launch changes the PeerId and breaks any saved `/p2p/<peer_id>` multiaddr.
+// Keys are stored in the same protobuf encoding libp2p uses on the wire so the files are
+// interchangeable with other libp2p tooling.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use libp2p::{identity, PeerId};
+
+use crate::CliArguments;
+
+const IDENTITY_FILE_NAME: &str = "identity.key";
+
+// Where the keys of a running node came from, shown in the instance info view.
+#[derive(Debug, Clone)]
+pub enum IdentitySource {
+    // Loaded from an existing key file
+    File(PathBuf),
+    // No key file existed so one was generated and saved
+    Created(PathBuf),
+    // Random keys that are not saved, the PeerId changes every run
+    Ephemeral,
+    // Derived from --identity-seed so the PeerId is known ahead of time
+    Seed(IdentitySeed),
+    // Not a live node, the PeerId of the node that made a recording being replayed
+    Recording(PathBuf),
+    // The node is a daemon the TUI is attached to, identity describes the keys of the daemon
+    Attached { socket: PathBuf, identity: String },
+}
+
+impl fmt::Display for IdentitySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentitySource::File(path) => write!(f, "key file {}", path.display()),
+            IdentitySource::Created(path) => write!(f, "new key file {}", path.display()),
+            IdentitySource::Ephemeral => write!(f, "ephemeral"),
+            IdentitySource::Seed(seed) => write!(f, "seed {seed} (not for production use)"),
+            IdentitySource::Recording(path) => write!(f, "replay of {}", path.display()),
+            IdentitySource::Attached { socket, identity } => {
+                write!(f, "{identity}, attached through {}", socket.display())
+            }
+        }
+    }
+}
+
+// Default key file location, e.g. ~/.config/p2p-scope/identity.key on Linux.
+pub fn default_identity_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("p2p-scope").join(IDENTITY_FILE_NAME))
+}
+
+// Resolve the keypair for this run from the command line options.
+pub fn node_identity(
+    args: &CliArguments,
+) -> Result<(identity::Keypair, IdentitySource), Box<dyn Error>> {
+    if let Some(seed) = &args.identity_seed {
+        return Ok((seed.keypair()?, IdentitySource::Seed(seed.clone())));
+    }
+    if args.ephemeral {
+        return Ok((identity::Keypair::generate_ed25519(), IdentitySource::Ephemeral));
+    }
+    let path = key_file_path(args.identity.clone())?;
+    load_or_create_identity(&path)
+}
+
+pub fn load_or_create_identity(
+    path: &Path,
+) -> Result<(identity::Keypair, IdentitySource), Box<dyn Error>> {
+    if path.exists() {
+        let keypair = read_identity(path)?;
+        Ok((keypair, IdentitySource::File(path.to_path_buf())))
+    } else {
+        let keypair = identity::Keypair::generate_ed25519();
+        write_identity(path, &keypair, false)?;
+        Ok((keypair, IdentitySource::Created(path.to_path_buf())))
+    }
+}
+
+pub fn read_identity(path: &Path) -> Result<identity::Keypair, Box<dyn Error>> {
+    let bytes = fs::read(path)
+        .map_err(|e| format!("Unable to read identity file {}: {e}", path.display()))?;
+    let keypair = identity::Keypair::from_protobuf_encoding(&bytes)
+        .map_err(|e| format!("Invalid identity file {}: {e}", path.display()))?;
+    Ok(keypair)
+}
+
+// Refuses to replace an existing key file unless overwrite is set.
+// On unix the file is only readable by the owner since it holds the private key.
+pub fn write_identity(
+    path: &Path,
+    keypair: &identity::Keypair,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !overwrite && path.exists() {
+        return Err(format!("Identity file {} already exists", path.display()).into());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let bytes = keypair.to_protobuf_encoding()?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Unable to write identity file {}: {e}", path.display()))?;
+    file.write_all(&bytes)?;
+    Ok(())
+}
+
+// keygen subcommand, works on key files without starting the swarm.
+pub fn run_keygen(command: KeygenCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        KeygenCommand::New { path, force } => {
+            let path = key_file_path(path)?;
+            let keypair = identity::Keypair::generate_ed25519();
+            write_identity(&path, &keypair, force)?;
+            println!("Created {}", path.display());
+            println!("Peer ID: {}", PeerId::from(keypair.public()));
+        }
+        KeygenCommand::Inspect { path } => {
+            let path = key_file_path(path)?;
+            let keypair = read_identity(&path)?;
+            let public_key = keypair.public();
+            println!("File: {}", path.display());
+            match keypair.try_into_ed25519() {
+                Ok(ed25519_keys) => {
+                    println!("Key type: ed25519");
+                    println!("Public key: {}", to_hex(&ed25519_keys.public().to_bytes()));
+                }
+                Err(_) => println!("Key type: unsupported"),
+            }
+            println!("Peer ID: {}", PeerId::from(public_key));
+        }
+        KeygenCommand::PeerId { path, seed } => {
+            let keypair = match seed {
+                Some(seed) => seed.keypair()?,
+                None => read_identity(&key_file_path(path)?)?,
+            };
+            println!("{}", PeerId::from(keypair.public()));
+        }
+    }
+    Ok(())
+}
+
+fn key_file_path(path: Option<PathBuf>) -> Result<PathBuf, Box<dyn Error>> {
+    path.or_else(default_identity_path).ok_or_else(|| {
+        "No default configuration directory for the identity file, use --identity <path>".into()
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Secret key seed for reproducible test swarms.
+// A small number fills the first byte of the ed25519 secret like the libp2p examples,
+// so `--identity-seed 1` gives the same PeerId as `--secret-key-seed 1` there.
+// 64 hex digits give the whole 32 byte secret.
+// Anyone who knows the seed has the private key, only use it for tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentitySeed {
+    Byte(u8),
+    Secret([u8; 32]),
+}
+
+impl IdentitySeed {
+    pub fn keypair(&self) -> Result<identity::Keypair, Box<dyn Error>> {
+        let mut secret = [0u8; 32];
+        match self {
+            IdentitySeed::Byte(byte) => secret[0] = *byte,
+            IdentitySeed::Secret(bytes) => secret = *bytes,
+        }
+        Ok(identity::Keypair::ed25519_from_bytes(secret)?)
+    }
+}
+
+impl FromStr for IdentitySeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(byte) = s.parse::<u8>() {
+            return Ok(IdentitySeed::Byte(byte));
+        }
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        if digits.len() != 64 || !digits.is_ascii() {
+            return Err("expected a number from 0 to 255 or 64 hex digits".to_string());
+        }
+        // from_str_radix would also take a sign
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid hex digits in seed: {s}"));
+        }
+        let mut secret = [0u8; 32];
+        for (i, byte) in secret.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("invalid hex digits in seed: {s}"))?;
+        }
+        Ok(IdentitySeed::Secret(secret))
+    }
+}
+
+impl fmt::Display for IdentitySeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentitySeed::Byte(byte) => write!(f, "{byte}"),
+            IdentitySeed::Secret(bytes) => write!(f, "0x{}", to_hex(bytes)),
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum KeygenCommand {
+    /// Generate a new ed25519 key file. Uses the default identity file if no path is given.
+    New {
+        path: Option<PathBuf>,
+        #[arg(long)]
+        /// Replace the key file if it already exists. The old PeerId is lost!
+        force: bool,
+    },
+    /// Show the key type, public key and PeerId of a key file.
+    Inspect { path: Option<PathBuf> },
+    /// Print only the PeerId of a key file or seed, handy for building /p2p/ multiaddrs in scripts.
+    PeerId {
+        path: Option<PathBuf>,
+        #[arg(long, conflicts_with = "path", value_name = "u8|HEX")]
+        /// Print the PeerId --identity-seed would give instead of reading a file.
+        seed: Option<IdentitySeed>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer_id(seed: &str) -> PeerId {
+        let seed: IdentitySeed = seed.parse().unwrap();
+        seed.keypair().unwrap().public().to_peer_id()
+    }
+
+    #[test]
+    fn seed_1_matches_the_libp2p_examples() {
+        let expected = "12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X";
+        assert_eq!(peer_id("1").to_string(), expected);
+    }
+
+    #[test]
+    fn hex_seeds_are_the_secret_key() {
+        let seed = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let expected = "12D3KooWA4Xop1JaT3MHxwYMkCepYsv4iPVopMXwCz5iHYdBfeSB";
+        assert_eq!(peer_id(seed).to_string(), expected);
+        assert_eq!(peer_id(&format!("0x{}", seed.to_uppercase())).to_string(), expected);
+        // a byte seed is the first byte of the secret
+        let one = "0x0100000000000000000000000000000000000000000000000000000000000000";
+        assert_eq!(peer_id(one), peer_id("1"));
+    }
+
+    #[test]
+    fn rejects_invalid_seeds() {
+        let table = [
+            "256",
+            "-1",
+            "",
+            "0x",
+            // 63 and 65 digits
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1",
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f0",
+            "zz0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            "+00102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            // 64 bytes but not 64 digits
+            "éééééééééééééééééééééééééééééééé",
+        ];
+        for seed in table {
+            assert!(seed.parse::<IdentitySeed>().is_err(), "{seed}");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Listening address bookkeeping for the listeners view.
+// The network loop feeds the swarm listener events in here and forwards the resulting
+// UiUpdate. Addresses carry the /p2p/<peer id> suffix so they can be handed to another
+// node as a complete dial address.
+// Listeners are numbered here in the order they report, a libp2p ListenerId is random
+// and can not be recorded and replayed.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::SystemTime;
+
+use libp2p::core::transport::ListenerId;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::ui_api::UiUpdate;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ListenAddrState {
+    Listening,
+    // The listener still runs but no longer reports this address, e.g. an interface went down
+    Expired,
+    // The listener stopped, with the error if it did not stop cleanly
+    Closed(Option<String>),
+}
+
+impl fmt::Display for ListenAddrState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddrState::Listening => write!(f, "listening"),
+            ListenAddrState::Expired => write!(f, "expired"),
+            ListenAddrState::Closed(None) => write!(f, "closed"),
+            ListenAddrState::Closed(Some(_)) => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenAddrInfo {
+    pub listener: u64,
+    // Listening address including the /p2p/<peer id> suffix
+    pub address: Multiaddr,
+    pub state: ListenAddrState,
+    pub since: SystemTime,
+    // Last non-fatal error the listener reported
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ListenerTracker {
+    local_peer_id: PeerId,
+    listeners: HashMap<ListenerId, u64>,
+    addresses: Vec<ListenAddrInfo>,
+}
+
+impl ListenerTracker {
+    pub fn new(local_peer_id: PeerId) -> Self {
+        ListenerTracker {
+            local_peer_id,
+            listeners: HashMap::new(),
+            addresses: Vec::new(),
+        }
+    }
+
+    pub fn new_listen_addr(&mut self, listener_id: ListenerId, address: Multiaddr) -> UiUpdate {
+        let address = with_p2p_suffix(address, self.local_peer_id);
+        let next_number = self.listeners.len() as u64 + 1;
+        let listener = *self.listeners.entry(listener_id).or_insert(next_number);
+        let state = ListenAddrState::Listening;
+        match self.find(listener, &address) {
+            Some(index) => {
+                let info = &mut self.addresses[index];
+                info.state = state;
+                info.since = SystemTime::now();
+                UiUpdate::ListenAddrChanged(info.clone())
+            }
+            None => {
+                let info = ListenAddrInfo {
+                    listener,
+                    address,
+                    state,
+                    since: SystemTime::now(),
+                    last_error: None,
+                };
+                self.addresses.push(info.clone());
+                UiUpdate::ListenAddrChanged(info)
+            }
+        }
+    }
+
+    pub fn expired_listen_addr(
+        &mut self,
+        listener_id: ListenerId,
+        address: Multiaddr,
+    ) -> Option<UiUpdate> {
+        let address = with_p2p_suffix(address, self.local_peer_id);
+        let listener = *self.listeners.get(&listener_id)?;
+        let index = self.find(listener, &address)?;
+        Some(self.set_state(index, ListenAddrState::Expired))
+    }
+
+    pub fn listener_closed(
+        &mut self,
+        listener_id: ListenerId,
+        reason: &Result<(), io::Error>,
+    ) -> Vec<UiUpdate> {
+        let state = ListenAddrState::Closed(reason.as_ref().err().map(|e| e.to_string()));
+        let indices: Vec<usize> = self.indices_of(listener_id).collect();
+        indices
+            .into_iter()
+            .map(|index| self.set_state(index, state.clone()))
+            .collect()
+    }
+
+    // The error is kept on every address of the listener, the listener itself keeps running
+    pub fn listener_error(&mut self, listener_id: ListenerId, error: &io::Error) -> Vec<UiUpdate> {
+        let indices: Vec<usize> = self.indices_of(listener_id).collect();
+        indices
+            .into_iter()
+            .map(|index| {
+                let info = &mut self.addresses[index];
+                info.last_error = Some(error.to_string());
+                UiUpdate::ListenAddrChanged(info.clone())
+            })
+            .collect()
+    }
+
+    // The listeners that reported an address, for closing them at shutdown
+    pub fn listener_ids(&self) -> Vec<ListenerId> {
+        self.listeners.keys().copied().collect()
+    }
+
+    fn set_state(&mut self, index: usize, state: ListenAddrState) -> UiUpdate {
+        let info = &mut self.addresses[index];
+        info.state = state;
+        info.since = SystemTime::now();
+        UiUpdate::ListenAddrChanged(info.clone())
+    }
+
+    fn find(&self, listener: u64, address: &Multiaddr) -> Option<usize> {
+        self.addresses
+            .iter()
+            .position(|a| a.listener == listener && a.address == *address)
+    }
+
+    // Nothing for a listener that never reported an address
+    fn indices_of(&self, listener_id: ListenerId) -> impl Iterator<Item = usize> + '_ {
+        let listener = self.listeners.get(&listener_id).copied();
+        self.addresses
+            .iter()
+            .enumerate()
+            .filter(move |(_, a)| Some(a.listener) == listener)
+            .map(|(index, _)| index)
+    }
+}
+
+// Appends /p2p/<peer id> unless the address already names a peer
+pub fn with_p2p_suffix(address: Multiaddr, peer_id: PeerId) -> Multiaddr {
+    if address.iter().any(|p| matches!(p, Protocol::P2p(_))) {
+        address
+    } else {
+        address.with(Protocol::P2p(peer_id.into()))
+    }
+}
@@ -30,33 +30,73 @@
 //! ```
 
 
+mod behaviour;
+#[cfg(unix)]
+mod control;
+mod cursive_dial_targets_view;
+mod cursive_events_view;
+mod cursive_listeners_view;
+mod cursive_peers_view;
+mod cursive_theme;
 mod cursive_tui;
-
-use crate::cursive_tui::{UiUpdate, CursiveCallback,
-                         ui_update_to_cursive_callback,
-                         terminal_user_interface};
+mod dial_targets;
+mod dns;
+#[cfg(unix)]
+mod daemon;
+mod frontend;
+mod headless;
+mod identity_store;
+mod listeners;
+mod peers;
+mod recording;
+mod replay;
+mod scope_command;
+mod scope_event;
+mod settings;
+mod shutdown;
+mod swarm_key;
+mod transport;
+mod ui_api;
+mod update_stream;
+#[cfg(feature = "web")]
+mod web;
+
+use crate::dial_targets::{DialTargetState, DialTargets, RedialOptions};
+use crate::dns::DnsLayers;
+use crate::frontend::{CommandSender, NodeInfo};
+use crate::behaviour::{
+    message_text, AppBehaviour, AppBehaviourEvent, GossipsubOptions, PubsubMode,
+};
+use crate::identity_store::{IdentitySeed, KeygenCommand};
+use crate::listeners::ListenerTracker;
+use crate::peers::PeerTracker;
+use crate::recording::Recorder;
+use crate::replay::ReplayCommand;
+use crate::scope_event::{EventCategory, SwarmEventLog};
+use crate::settings::{ConfigCommand, KeyBindings};
+use crate::shutdown::Shutdown;
+use crate::swarm_key::{PnetHandshakes, SwarmKeyCommand};
+use crate::transport::{NegotiatedMuxers, TransportOptions};
+use crate::ui_api::{StateQuery, UiCommand, UiUpdate, DEFAULT_TOPIC};
 // Lib p2p and related includes
-use libp2p::core::{ConnectedPoint};
-use libp2p::swarm::ConnectionError::KeepAliveTimeout;
 pub(crate) use libp2p::{
-    core::upgrade,
-    floodsub::{self, Floodsub, FloodsubEvent},
+    floodsub::FloodsubEvent,
     futures::StreamExt,
-    identity, mdns, mplex, noise,
-    swarm::{keep_alive, NetworkBehaviour, Swarm, SwarmEvent},
-    tcp, Multiaddr, PeerId, Transport,
+    gossipsub, mdns,
+    core::transport::TransportError,
+    swarm::{DialError, ListenError, Swarm, SwarmBuilder, SwarmEvent},
+    Multiaddr, PeerId,
 };
 
 use std::error::Error;
-use tokio;
-use tokio::io::AsyncBufReadExt;
-use std::sync::mpsc;
-use std::thread::sleep;
-use std::time::Duration;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use libp2p::floodsub::FloodsubMessage;
 // Command line arguments defined for clap at the end of this file
-use clap::Parser;
-use cursive::CbSink;
-use libp2p::swarm::KeepAlive;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use serde::{Deserialize, Serialize};
 
 
 #[tokio::main]
@@ -64,160 +104,107 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // todo:Init Tracing
     
 
-    //parse command line arguments
+    //parse command line arguments, the settings file fills in what they leave out
+    let matches = CliArguments::command().get_matches();
+    let mut clap_args = CliArguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Err(e) = clap_args.redial.check() {
+        CliArguments::command().error(clap::error::ErrorKind::ValueValidation, e).exit();
+    }
+    settings::apply_settings_file(&mut clap_args, &matches)?;
 
-    let clap_args = CliArguments::parse();
-    let args_text = format!("cli args: {:?}", clap_args);
+    // Subcommands run instead of the swarm
+    match clap_args.command.clone() {
+        Some(CliCommand::Keygen(keygen_command)) => {
+            return identity_store::run_keygen(keygen_command);
+        }
+        Some(CliCommand::Config(config_command)) => {
+            return settings::run_config(config_command, &clap_args);
+        }
+        Some(CliCommand::SwarmKey(swarm_key_command)) => {
+            return swarm_key::run_swarm_key(swarm_key_command);
+        }
+        Some(CliCommand::Replay(replay_command)) => {
+            return replay::run_replay(replay_command, clap_args).await;
+        }
+        #[cfg(unix)]
+        Some(CliCommand::Ctl(ctl_command)) => {
+            return control::run_ctl(ctl_command).await;
+        }
+        #[cfg(unix)]
+        Some(CliCommand::Attach(attach_command)) => {
+            return daemon::run_attach(attach_command, clap_args).await;
+        }
+        #[cfg(unix)]
+        Some(CliCommand::Daemon(_)) => daemon::ignore_hangup()?,
+        None => {}
+    }
 
     // Initialize Lib-p2p instance information
-    // Create a random PeerId
-    let id_keys = identity::Keypair::generate_ed25519();
+    // Load the node keys so the PeerId is stable between runs
+    let (id_keys, identity_source) = identity_store::node_identity(&clap_args)?;
     let peer_id = PeerId::from(id_keys.public());
 
-
-    // Stage the channels and functions used to communicate between tokio and the UI thread
-    let (input_sender,mut input_receiver) =
-        tokio::sync::mpsc::channel::<Box<String>>(32);
-    //let (update_sender,mut update_receiver) =
-    //   std::sync::mpsc::channel::<Box<UiUpdate>>();
-    let (cb_sync_sender,
-        mut cb_sync_receiver) = tokio::sync::oneshot::channel();
-    let clap_args_clone = clap_args.clone(); //clone is a value to move
-    // A regular sync thread running along side of the tokio runtime.
-    let _tui_handle = std::thread::spawn(move || {
-        terminal_user_interface(input_sender.clone(),
-                                peer_id,
-                                clap_args_clone,
-                                cb_sync_sender);
-    });
-
-    let cb_sink = cb_sync_receiver.await.unwrap();// get callback channel from new thread
-
-    let cb_sink_clone = cb_sink.clone();// avoid loosing cb_sink
-    let terminal_output =move |output:String| {
-        cb_sink_clone.send(ui_update_to_cursive_callback(
-            UiUpdate::TerminalOutput(output))).unwrap();
+    // The swarm is built and starts listening before the UI starts so configuration errors
+    // are printed plainly.
+    let behaviour = AppBehaviour::new(&id_keys, clap_args.pubsub, &clap_args.gossipsub)?;
+    let recorder = match &clap_args.record {
+        Some(path) => Some(Recorder::create(path, peer_id)?),
+        None => None,
     };
 
-    let cb_sink_clone2 = cb_sink.clone();
-    let send_ui_update = |tui_update:UiUpdate|{
-        cb_sink_clone2.send(ui_update_to_cursive_callback(
-            tui_update)).unwrap()};
-
-    // Create a tokio-based TCP transport use noise for authenticated
-    // encryption and Mplex for multiplexing of substreams on a TCP stream.
-    let transport =
-        tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
-        .upgrade(upgrade::Version::V1)
-        .authenticate(
-            noise::NoiseAuthenticated::xx(&id_keys)
-                .expect("Signing libp2p-noise static DH keypair failed."),
-        )
-        .multiplex(mplex::MplexConfig::new())
-        .boxed();
-
-    // Create a Floodsub topic.  Note changed in scope version.
-    let floodsub_topic = floodsub::Topic::new("monolith");
-
-    // We create a custom  behaviour that combines floodsub and mDNS.
-    // The derive generates a delegating `NetworkBehaviour` impl.
-    #[derive(NetworkBehaviour)]
-    #[behaviour(out_event = "AppBehaviourEvent")]
-    struct AppBehaviour {
-        // keep_alive: keep_alive::Behaviour,
-        floodsub: Floodsub,
-        mdns: mdns::tokio::Behaviour,
-    }
-
-
-    #[derive(Debug)]
-    #[allow(clippy::large_enum_variant)]
-    enum AppBehaviourEvent {
-        // KeepAlive(KeepAlive),
-        Floodsub(FloodsubEvent),
-        Mdns(mdns::Event),
-    }
-
-    // impl From<KeepAlive> for AppBehaviourEvent{
-    //     fn from(event: KeepAlive) -> Self {
-    //         AppBehaviourEvent::KeepAlive(event)
-    //     }
-    // }
-
-    impl From<FloodsubEvent> for AppBehaviourEvent {
-        fn from(event: FloodsubEvent) -> Self {
-            AppBehaviourEvent::Floodsub(event)
-        }
-    }
-
-    impl From<mdns::Event> for AppBehaviourEvent {
-        fn from(event: mdns::Event) -> Self {
-            AppBehaviourEvent::Mdns(event)
-        }
-    }
+    let muxers = NegotiatedMuxers::default();
+    // to explain failed handshakes in a private network
+    let pnet_handshakes = PnetHandshakes::default();
+    let swarm_key_fingerprint = match &clap_args.transport.swarm_key {
+        Some(path) => Some(swarm_key::read_swarm_key(path)?.fingerprint()),
+        None => None,
+    };
+    // the lookups of dials to /dns addresses go to the event log
+    let (dns_sender, mut dns_summaries) = mpsc::unbounded_channel();
+    let dns = DnsLayers::new(&clap_args.transport.dns_resolver, dns_sender)?;
+    let transport = transport::build_transport(
+        &id_keys, &clap_args.transport, &muxers, &dns, &pnet_handshakes)?;
 
     // Create a Swarm to manage peers and events.
-    let mdns_behaviour =
-        mdns::Behaviour::new(Default::default(), peer_id)?;
-    // let stay_alive = keep_alive::Behaviour::new(libp2p::swarm::KeepAlive::Yes);
-    let behaviour = AppBehaviour {
-        // keep_alive: stay_alive,
-        floodsub: Floodsub::new(peer_id),
-        mdns: mdns_behaviour,
-    };
     let mut swarm =
-        Swarm::with_tokio_executor(transport, behaviour, peer_id);
-    swarm
-        .behaviour_mut()
-        .floodsub
-        .subscribe(floodsub_topic.clone());
+        SwarmBuilder::with_tokio_executor(transport, behaviour, peer_id).build();
 
-    // Reach out to another node if specified
-    match clap_args.dial {
-        Some(addr_list) => {
-            for addr in addr_list {
-                swarm.dial(addr.clone()).unwrap();
-                (terminal_output)(format!("Dialed {:?}", addr));
-            }
-        }
-        None => {
-            (terminal_output)(format!("No addresses Dialed"));
-        }
-    }
     // Replaced by Tui
     // Read full lines from stdin
     // let mut stdin = io::BufReader::new(io::stdin()).lines();
     // Listen mode takes president over listen which can be given multiple times.
     // Listening on all networks is the default if neither are specified
-    let all_nets_addr :Multiaddr = "/ip4/0.0.0.0/tcp/0".parse()?;
-    let localhost_addr :Multiaddr = "/ip4/127.0.0.1/tcp/0".parse()?;
-    let mut no_listen = false;
-    let mut default_listen_all= false;
+    // One address for each transport in --transport
+    let all_nets_addrs = clap_args.transport.listen_addrs(Ipv4Addr::UNSPECIFIED);
+    let localhost_addrs = clap_args.transport.listen_addrs(Ipv4Addr::LOCALHOST);
 
-    if let None = clap_args.listen_mode{
-        if let Some(addrs_vec) = clap_args.listen {
+    if clap_args.listen_mode.is_none() {
+        if let Some(addrs_vec) = &clap_args.listen {
             for addr in addrs_vec{
                 swarm.listen_on(addr.clone())?;
             }
         } else {
             // no listen mode or specified addr/ port so default to all!
-            default_listen_all = true;
-            swarm.listen_on(all_nets_addr.clone())?;
+            for addr in &all_nets_addrs {
+                swarm.listen_on(addr.clone())?;
+            }
         }
     }
 
-    if let Some(ListenMode) = clap_args.listen_mode {
-        match ListenMode {
+    if let Some(listen_mode) = &clap_args.listen_mode {
+        match listen_mode {
             // Listen on all interfaces and whatever port the OS assigns
             ListenMode::All => {
-                swarm.listen_on(all_nets_addr.clone())?;
-            }
-            ListenMode::DoNotListen => {
-                (terminal_output)(format!("Not listening! La! La! La!"));
-                no_listen = true;
+                for addr in &all_nets_addrs {
+                    swarm.listen_on(addr.clone())?;
+                }
             }
+            // said once the UI runs
+            ListenMode::DoNotListen => {}
             ListenMode::Localhost => {
-                swarm.listen_on(localhost_addr.clone())?;
+                for addr in &localhost_addrs {
+                    swarm.listen_on(addr.clone())?;
+                }
             }
             // ListenMode::Lan => {
             //     swarm.listen_on(all_ports)?;
@@ -226,107 +213,518 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    let listeners = swarm.listeners();
-    (terminal_output)("LISTENERS:\r".to_string());
-    for ma in listeners {
-        (terminal_output)(format!("{:?}\r", ma));
+    // Stage the channel the frontends send commands on and start them
+    let (command_sender, mut command_receiver) = CommandSender::channel(32);
+    shutdown::spawn_signal_handler(command_sender.clone());
+    let node_info = NodeInfo { peer_id, identity_source, args: clap_args.clone() };
+    let mut frontends = frontend::frontends_for(&clap_args);
+    frontends.start(&node_info, command_sender).await?;
+
+    // Everything shown in the UI goes through send_ui_update so --record sees all of it
+    let send_ui_update = |tui_update:UiUpdate|{
+        if let Some(Err(e)) = recorder.as_ref().map(|r| r.record(&tui_update)) {
+            frontends.update(UiUpdate::CommandError(e.to_string()));
+        }
+        frontends.update(tui_update)};
+    let terminal_output = |output:String| {
+        (send_ui_update)(UiUpdate::TerminalOutput(output));
+    };
+    #[cfg(feature = "web")]
+    if let Some(address) = clap_args.web {
+        (terminal_output)(format!("Web dashboard on http://{address}"));
+    }
+    #[cfg(unix)]
+    if let Some(path) = control::control_socket(&clap_args) {
+        (terminal_output)(format!("Control socket {}", path.display()));
+        // without a frontend of its own the daemon says once where to find it
+        if let Some(CliCommand::Daemon(_)) = &clap_args.command {
+            println!("Peer ID: {} Identity: {}", node_info.peer_id, node_info.identity_source);
+            println!("Control socket {}, watch the node with attach and stop it with ctl shutdown",
+                     path.display());
+        }
+    }
+
+    if let Some(ListenMode::DoNotListen) = clap_args.listen_mode {
+        (terminal_output)("Not listening! La! La! La!".to_string());
+    }
+
+    // Join the pubsub topics, the UI adds a chat panel for each.
+    // Floodsub has no way to ask which topics it is subscribed to so they are kept here.
+    let mut topics: Vec<String> = Vec::new();
+    let mut peer_tracker = PeerTracker::default();
+    let mut listener_tracker = ListenerTracker::new(peer_id);
+    let mut dial_targets = DialTargets::new(clap_args.redial.clone());
+    let mut event_log = SwarmEventLog::default();
+    for topic in &clap_args.topics {
+        let subscribe = UiCommand::Subscribe(topic.clone());
+        match run_ui_command(&mut swarm, &mut topics, &mut dial_targets, subscribe) {
+            Ok(Some(reply)) => (send_ui_update)(reply),
+            Ok(None) => {}
+            Err(e) => (send_ui_update)(UiUpdate::CommandError(e.to_string())),
+        }
     }
 
+    // Reach out to another node if specified, and again whenever the connection is lost
+    match clap_args.dial {
+        Some(addr_list) => {
+            for addr in addr_list {
+                match dial_targets.add(&mut swarm, addr.clone()) {
+                    Ok(update) => {
+                        // a dial that failed at once is backing off already
+                        if matches!(&update, UiUpdate::DialTargetChanged(info)
+                            if info.state == DialTargetState::Dialing)
+                        {
+                            (terminal_output)(format!("Dialing {addr}"));
+                        }
+                        (send_ui_update)(update)
+                    }
+                    Err(e) => (send_ui_update)(UiUpdate::CommandError(e)),
+                }
+            }
+        }
+        None => {
+            (terminal_output)("No addresses Dialed".to_string());
+        }
+    }
     // Kick it off
+    let mut shutdown: Option<Shutdown> = None;
+    let mut commands_closed = false;
+    // set when CLOSE_TIMEOUT passed before everything was closed
+    let mut close_timed_out = false;
     loop {
+        let shutdown_deadline = shutdown.as_ref().map(Shutdown::deadline);
+        // nothing is redialed once the node shuts down
+        let redial_at = dial_targets.next_attempt().filter(|_| shutdown.is_none());
         tokio::select! {
-            Some(box_message) = input_receiver.recv() => {
-                let message = *box_message;
-                swarm.behaviour_mut().floodsub.publish_any(
-                    floodsub_topic.clone(), message);
+            command = command_receiver.recv(), if !commands_closed => {
+                let (command, reply_sender) = match command {
+                    Some(command) => command,
+                    // every frontend is gone when the channel closes
+                    None => {
+                        commands_closed = true;
+                        (UiCommand::Shutdown, None)
+                    }
+                };
+                if command == UiCommand::Shutdown {
+                    if shutdown.is_none() {
+                        let (started, updates) = Shutdown::begin(&mut swarm, &listener_tracker,
+                            &topics, clap_args.goodbye.as_deref());
+                        updates.into_iter().for_each(send_ui_update);
+                        // without peers or listeners there is nothing to wait for
+                        if started.finished(&swarm) {
+                            break;
+                        }
+                        shutdown = Some(started);
+                    }
+                    continue;
+                }
+                let reply = if shutdown.is_some() {
+                    Err("The node is shutting down".to_string())
+                } else {
+                    run_ui_command(&mut swarm, &mut topics, &mut dial_targets, command)
+                        .map_err(|e| e.to_string())
+                };
+                match &reply {
+                    Ok(Some(update)) => (send_ui_update)(update.clone()),
+                    Ok(None) => {}
+                    Err(e) => (send_ui_update)(UiUpdate::CommandError(e.clone())),
+                }
+                // a request also gets the reply back on its own
+                if let Some(reply_sender) = reply_sender {
+                    let _ = reply_sender.send(reply);
+                }
+            }
+            _ = tokio::time::sleep_until(shutdown_deadline.unwrap_or_else(Instant::now)),
+                if shutdown_deadline.is_some() => {
+                let Some(current) = shutdown.take() else { continue };
+                let (next, updates) = current.next(&mut swarm, &listener_tracker);
+                updates.into_iter().for_each(send_ui_update);
+                match next {
+                    Some(next) => shutdown = Some(next),
+                    None => {
+                        close_timed_out = true;
+                        break;
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(redial_at.unwrap_or_else(Instant::now)),
+                if redial_at.is_some() => {
+                dial_targets.redial_due(&mut swarm).into_iter().for_each(send_ui_update);
             }
-            //Todo:handle other messages, terminate message, topics, layout changes,
-            //  event list, menubar, text commands.
+            Some(summary) = dns_summaries.recv() => {
+                (send_ui_update)(UiUpdate::Event(
+                    event_log.note(EventCategory::Dial, None, summary)));
+            }
+            //Todo:handle other messages, topics, layout changes,
+            //  event list, menubar.
             event = swarm.select_next_some() => {
+                (send_ui_update)(UiUpdate::Event(event_log.record(&event)));
                 match event {
-                    SwarmEvent::NewListenAddr { address, .. } => {
+                    SwarmEvent::NewListenAddr { listener_id, address } => {
                         (terminal_output)(format!("Listening on {address:?}"));
+                        (send_ui_update)(listener_tracker.new_listen_addr(listener_id, address));
+                    }
+                    SwarmEvent::ExpiredListenAddr { listener_id, address } => {
+                        (terminal_output)(format!("Expired listen address {address:?}"));
+                        if let Some(update) = listener_tracker.expired_listen_addr(
+                            listener_id, address) {
+                            (send_ui_update)(update);
+                        }
+                    }
+                    SwarmEvent::ListenerClosed { listener_id, reason, .. } => {
+                        match &reason {
+                            Ok(()) => (terminal_output)(format!("Listener {listener_id:?} closed")),
+                            Err(e) => (send_ui_update)(UiUpdate::CommandError(
+                                format!("Listener {listener_id:?} closed: {e}"))),
+                        }
+                        for update in listener_tracker.listener_closed(listener_id, &reason) {
+                            (send_ui_update)(update);
+                        }
+                    }
+                    SwarmEvent::ListenerError { listener_id, error } => {
+                        (send_ui_update)(UiUpdate::CommandError(
+                            format!("Listener {listener_id:?} error: {error}")));
+                        for update in listener_tracker.listener_error(listener_id, &error) {
+                            (send_ui_update)(update);
+                        }
                     }
                     SwarmEvent::Behaviour(AppBehaviourEvent::Floodsub(
                         FloodsubEvent::Message(message))) => {
-                        let message_string = String::from_utf8(message.data).unwrap();
-                        // let t =message.topics;
-                        // ToDo: learn about the Vec<Topics> part of the Floodsub message. convert.
+                        let FloodsubMessage { source, data, topics: message_topics, .. } = message;
+                        let message_string = message_text(data);
+                        // A floodsub message can be sent to several topics at once,
+                        // show it in the panel of each one we are subscribed to.
+                        for topic in message_topics.iter().map(|t| t.id())
+                            .filter(|id| topics.iter().any(|t| t == id)) {
+                            (send_ui_update)(
+                                UiUpdate::TextMessage(topic.to_string(),
+                                    source,
+                                    message_string.clone())
+                            );
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Gossipsub(
+                        gossipsub::Event::Message{propagation_source, message, ..})) => {
+                        let message_string = message_text(message.data);
+                        // anonymous messages have no source, show who forwarded it instead
                         (send_ui_update)(
-                            UiUpdate::TextMessage( String::from("monolith"),
-                                message.source,
+                            UiUpdate::TextMessage(message.topic.to_string(),
+                                message.source.unwrap_or(propagation_source),
                                 message_string)
                         );
                     }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Floodsub(
+                        FloodsubEvent::Subscribed { peer_id, topic })) => {
+                        if let Some(update) = peer_tracker.topic_subscribed(
+                            peer_id, topic.id().to_string()) {
+                            (send_ui_update)(update);
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Floodsub(
+                        FloodsubEvent::Unsubscribed { peer_id, topic })) => {
+                        if let Some(update) = peer_tracker.topic_unsubscribed(peer_id, topic.id()) {
+                            (send_ui_update)(update);
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Gossipsub(
+                        gossipsub::Event::Subscribed { peer_id, topic })) => {
+                        if let Some(update) = peer_tracker.topic_subscribed(
+                            peer_id, topic.to_string()) {
+                            (send_ui_update)(update);
+                        }
+                    }
+                    SwarmEvent::Behaviour(AppBehaviourEvent::Gossipsub(
+                        gossipsub::Event::Unsubscribed { peer_id, topic })) => {
+                        if let Some(update) = peer_tracker.topic_unsubscribed(
+                            peer_id, topic.as_str()) {
+                            (send_ui_update)(update);
+                        }
+                    }
                     SwarmEvent::Behaviour(AppBehaviourEvent::Mdns(event)) => {
                         match event {
                             mdns::Event::Discovered(list) => {
                                 for (peer, _) in list {
-                                    swarm.behaviour_mut().floodsub.add_node_to_partial_view(peer);
+                                    swarm.behaviour_mut().peer_discovered(peer);
                                 }
                             }
                             mdns::Event::Expired(list) => {
                                 for (peer, _) in list {
                                     if !swarm.behaviour().mdns.has_node(&peer) {
-                                        swarm.behaviour_mut()
-                                        .floodsub.remove_node_from_partial_view(&peer);
+                                        swarm.behaviour_mut().peer_expired(&peer);
                                     }
                                 }
                             }
                         }
                     }
-                    SwarmEvent::ConnectionEstablished{peer_id,..} => {
-                        (terminal_output)(format!("Connected!: '{:?}'",event));
-                        swarm.behaviour_mut().floodsub.add_node_to_partial_view(peer_id);
+                    SwarmEvent::ConnectionEstablished{peer_id, ref endpoint, num_established, ..} => {
+                        let muxer = muxers.take(peer_id, endpoint).unwrap_or("unknown");
+                        pnet_handshakes.take(endpoint.get_remote_address());
+                        (terminal_output)(format!("Connection to {peer_id} at {} uses {muxer}",
+                            endpoint.get_remote_address()));
+                        (send_ui_update)(peer_tracker.connection_established(
+                            peer_id, endpoint, num_established, muxer));
+                        swarm.behaviour_mut().peer_connected(peer_id);
+                        dial_targets.connection_established(peer_id, endpoint)
+                            .into_iter().for_each(send_ui_update);
                     }
-                    SwarmEvent::ConnectionClosed {
-                        peer_id,
-                        endpoint: ConnectedPoint::Dialer { address,.. },
-                        cause: Some(KeepAliveTimeout),..} => {
-                        swarm.behaviour_mut().floodsub.remove_node_from_partial_view(&peer_id);
-                        // Hanging up so rude! Redial !
-                        // maybe a goodbye message. I believe this will only retry once.
-                        (terminal_output)(format!("KeepAliveTimeout, Redialing {:?}",address));
-                        swarm.dial(address)?;
+                    // Hanging up so rude! Sticky dial targets are redialed after a backoff.
+                    SwarmEvent::ConnectionClosed {peer_id, ref endpoint, num_established, cause, ..} =>{
+                        (send_ui_update)(peer_tracker.connection_closed(
+                            peer_id, endpoint, num_established));
+                        swarm.behaviour_mut().peer_disconnected(&peer_id);
+                        dial_targets.connection_closed(peer_id, num_established,
+                            cause.map(|c| c.to_string()))
+                            .into_iter().for_each(send_ui_update);
                     }
-                    SwarmEvent::ConnectionClosed {peer_id,..} =>{
-                        swarm.behaviour_mut().floodsub.remove_node_from_partial_view(&peer_id);
-                        (terminal_output)(format!("CLOSED:{:?}", event));
+                    SwarmEvent::IncomingConnectionError { ref send_back_addr, ref error, .. } => {
+                        muxers.forget(None, &[send_back_addr]);
+                        let through_pnet = pnet_handshakes.take(send_back_addr);
+                        if let ListenError::Transport(TransportError::Other(error)) = error {
+                            if let Some(hint) =
+                                swarm_key::key_mismatch(swarm_key_fingerprint, through_pnet, error) {
+                                (send_ui_update)(UiUpdate::CommandError(
+                                    format!("Handshake with {send_back_addr} failed, {hint}")));
+                            }
+                        }
                     }
-                    other_swarm_event => {
-                        (terminal_output)(format!("EVENT: {:?}",other_swarm_event));
+                    SwarmEvent::OutgoingConnectionError { peer_id, ref error } => {
+                        match error {
+                            DialError::Transport(errors) => {
+                                muxers.forget(peer_id,
+                                    &errors.iter().map(|(address, _)| address).collect::<Vec<_>>());
+                                for (address, error) in errors {
+                                    let through_pnet = pnet_handshakes.take(address);
+                                    let TransportError::Other(error) = error else { continue };
+                                    if let Some(hint) = swarm_key::key_mismatch(
+                                        swarm_key_fingerprint, through_pnet, error) {
+                                        (send_ui_update)(UiUpdate::CommandError(
+                                            format!("Handshake with {address} failed, {hint}")));
+                                    }
+                                }
+                            }
+                            DialError::WrongPeerId { obtained, endpoint } => {
+                                muxers.forget(Some(*obtained), &[endpoint.get_remote_address()]);
+                                pnet_handshakes.take(endpoint.get_remote_address());
+                            }
+                            DialError::LocalPeerId { endpoint } => {
+                                muxers.forget(None, &[endpoint.get_remote_address()]);
+                                pnet_handshakes.take(endpoint.get_remote_address());
+                            }
+                            // no addresses, e.g. Aborted or Denied
+                            _ => {
+                                muxers.forget(peer_id, &[]);
+                                if let Some(peer_id) = peer_id {
+                                    pnet_handshakes.forget_peer(peer_id);
+                                }
+                            }
+                        }
+                        let connection_id = swarm.behaviour_mut().dial_failures.take();
+                        dial_targets.dial_failed(connection_id, error)
+                            .into_iter().for_each(send_ui_update);
                     }
+                    // the rest is only shown in the event log
+                    _ => {}
                 }
             }
         }
+        if shutdown.as_ref().is_some_and(|s| s.finished(&swarm)) {
+            break;
+        }
+    }
+
+    (terminal_output)("Shut down".to_string());
+    let mut result: Result<(), Box<dyn Error>> = if close_timed_out {
+        Err("The node did not close within the shutdown timeout".into())
+    } else {
+        Ok(())
+    };
+    if let Some(recorder) = &recorder {
+        if let Err(e) = recorder.finish() {
+            (send_ui_update)(UiUpdate::CommandError(format!("Finishing the recording failed: {e}")));
+            result = Err(e);
+        }
     }
+    frontends.stop().await;
+    result
+}
+
+// Carry out a frontend command on the swarm, Shutdown is handled by the loop itself.
+fn run_ui_command(
+    swarm: &mut Swarm<AppBehaviour>,
+    topics: &mut Vec<String>,
+    dial_targets: &mut DialTargets,
+    command: UiCommand,
+) -> Result<Option<UiUpdate>, Box<dyn Error>> {
+    let result = match command {
+        UiCommand::Publish { topic, message } => {
+            swarm.behaviour_mut().publish(&topic, message)
+                .map_err(|e| format!("Publish to {topic} failed: {e}"))?;
+            return Ok(None);
+        }
+        UiCommand::Dial(addr) => {
+            swarm.dial(addr.clone())?;
+            format!("Dialing {addr}")
+        }
+        UiCommand::Stick(addr) => return Ok(Some(dial_targets.add(swarm, addr)?)),
+        UiCommand::Unstick(addr) => return Ok(Some(dial_targets.remove(&addr)?)),
+        UiCommand::Listen(addr) => {
+            let listener_id = swarm.listen_on(addr.clone())?;
+            format!("Listener {listener_id:?} starting on {addr}")
+        }
+        UiCommand::HangUp(peer_id) => {
+            swarm.disconnect_peer_id(peer_id)
+                .map_err(|_| format!("Not connected to {peer_id}"))?;
+            format!("Disconnecting {peer_id}")
+        }
+        UiCommand::Subscribe(topic) => {
+            if swarm.behaviour_mut().subscribe(&topic)? {
+                topics.push(topic.clone());
+                return Ok(Some(UiUpdate::Subscribed(topic)));
+            }
+            format!("Already subscribed to {topic}")
+        }
+        UiCommand::Unsubscribe(topic) => {
+            if swarm.behaviour_mut().unsubscribe(&topic)? {
+                topics.retain(|t| *t != topic);
+                return Ok(Some(UiUpdate::Unsubscribed(topic)));
+            }
+            format!("Not subscribed to {topic}")
+        }
+        UiCommand::Query(StateQuery::Peers) => {
+            return Ok(Some(UiUpdate::PeerList(swarm.connected_peers().copied().collect())));
+        }
+        UiCommand::Query(StateQuery::Listeners) => {
+            return Ok(Some(UiUpdate::ListenerList(swarm.listeners().cloned().collect())));
+        }
+        UiCommand::Query(StateQuery::Topics) => {
+            return Ok(Some(UiUpdate::TopicList(topics.clone())));
+        }
+        UiCommand::Shutdown => return Ok(None),
+        UiCommand::Replay(_) => {
+            return Err("Playback commands only work when replaying a recording".into());
+        }
+    };
+    Ok(Some(UiUpdate::CommandResult(result)))
 }
 
 // Argument parsing initialization
 #[derive(Parser, Default, Debug, Clone)]
 #[clap(author = "John Hall", version, about)]
 pub struct CliArguments {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+    #[arg(long, value_name = "FILE", global = true)]
+    /// TOML settings file, options given on the command line override it.
+    /// Defaults to <config dir>/p2p-scope/config.toml if it exists, see `config dump`.
+    config: Option<PathBuf>,
+    #[arg(long, value_name = "PATH")]
+    /// Protobuf encoded keypair file that gives this node a stable PeerId.
+    /// Created if it does not exist. Defaults to <config dir>/p2p-scope/identity.key
+    identity: Option<PathBuf>,
+    #[arg(long, conflicts_with = "identity")]
+    /// Use a random keypair that is not saved, the PeerId changes every run.
+    /// Needed when running several nodes as the same user.
+    ephemeral: bool,
+    #[arg(long, value_name = "u8|HEX", conflicts_with_all = ["identity", "ephemeral"])]
+    /// Derive the keypair from a seed so the PeerId is known ahead of time, for test swarms.
+    /// A number 0-255 matches the --secret-key-seed of the libp2p examples, or give 64 hex digits.
+    /// Anyone who knows the seed has the private key!
+    identity_seed: Option<IdentitySeed>,
+    #[arg(long, value_enum, default_value_t)]
+    /// Publish subscribe protocol for chat messages.
+    pubsub: PubsubMode,
+    #[command(flatten)]
+    gossipsub: GossipsubOptions,
+    #[arg(long = "topic", value_name = "TOPIC", default_value = DEFAULT_TOPIC)]
+    /// Pubsub topic to join, each gets its own chat panel. --topic may be given multiple times.
+    /// Chat messages go to the first one until changed with /topic.
+    topics: Vec<String>,
     #[arg(long, value_enum)]
     /// Takes president over listen which can be given multiple times.
     /// Listening on all networks is the default if neither are specified
     listen_mode: Option<ListenMode>,
-    /// Light ar dark theme can be picked. Default is light.
+    #[arg(long, value_enum)]
+    /// Colors of the TUI, dark by default. /theme switches while running.
     theme: Option<Theme>,
+    #[arg(long, value_name = "FILE", conflicts_with = "theme")]
+    /// Cursive theme file with the colors of the TUI, see src/theme_dark.toml for the format.
+    theme_file: Option<PathBuf>,
+    #[arg(long)]
+    /// Run without the TUI. UI updates are written to stdout as JSON Lines and commands are
+    /// read from stdin as one JSON value per line, e.g. {"Dial":"/ip4/127.0.0.1/tcp/4001"}.
+    headless: bool,
+    #[arg(long, requires = "headless")]
+    /// Shut the headless node down at the end of stdin instead of running until the Shutdown
+    /// command, Ctrl-C or SIGTERM.
+    exit_on_eof: bool,
+    #[arg(long, value_name = "MESSAGE")]
+    /// Publish MESSAGE to every subscribed topic when shutting down, e.g. "ATTENTION: I QUIT".
+    goodbye: Option<String>,
+    #[arg(long, value_name = "FILE")]
+    /// Record every UI update, including each swarm event, to FILE as JSON Lines.
+    /// Watch it again later with the replay subcommand.
+    record: Option<PathBuf>,
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    /// Accept JSON-RPC commands on a Unix domain socket, see the ctl subcommand.
+    /// Defaults to <runtime dir>/p2p-scope/control.sock, give a path as --control=PATH.
+    control: Option<Option<PathBuf>>,
+    #[cfg(feature = "web")]
+    #[arg(long, value_name = "ADDR")]
+    /// Serve a dashboard for the browser on ADDR, e.g. 127.0.0.1:8080.
+    /// Anyone who can reach ADDR can drive the node, keep it on localhost.
+    web: Option<std::net::SocketAddr>,
     #[arg(long)]
     /// Multiaddr to dial. --dial may be given multiple times.
+    /// The address is dialed again whenever the dial fails or the connection closes.
     dial: Option<Vec<Multiaddr>>,
+    #[command(flatten)]
+    redial: RedialOptions,
+    #[command(flatten)]
+    transport: TransportOptions,
+    // Only set in the settings file
+    #[arg(skip)]
+    keybindings: KeyBindings,
     /// Specify host network and port to listen on. May be given multiple times but is ignored
     /// if listen-mode is also given.
     listen: Option<Vec<Multiaddr>>,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::Subcommand, Clone, Debug)]
+pub(crate) enum CliCommand {
+    /// Create and inspect identity key files without starting the swarm.
+    #[command(subcommand)]
+    Keygen(KeygenCommand),
+    /// Inspect the configuration from the settings file and the command line.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Create and inspect the pre-shared key files of --swarm-key.
+    #[command(subcommand)]
+    SwarmKey(SwarmKeyCommand),
+    /// Play a recording made with --record in the TUI instead of running a node.
+    Replay(ReplayCommand),
+    /// Send a command to a node started with --control and print the reply.
+    #[cfg(unix)]
+    Ctl(control::CtlCommand),
+    /// Run the node without a TUI, only the control socket. Use attach to watch it.
+    /// Node options go before the subcommand, e.g. `--listen-mode localhost daemon`.
+    #[cfg(unix)]
+    Daemon(daemon::DaemonCommand),
+    /// Run the TUI against a running daemon. Quitting the TUI leaves the daemon running.
+    #[cfg(unix)]
+    Attach(daemon::AttachCommand),
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) enum Theme{
     Light,
+    #[default]
     Dark
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::ValueEnum, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) enum ListenMode {
     DoNotListen,
     All,
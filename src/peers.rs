@@ -0,0 +1,265 @@
+// Connected peer bookkeeping for the peers view.
+// The network loop feeds swarm and pubsub events in here and forwards the resulting
+// UiUpdate so a frontend can keep a live table of peers without asking the swarm.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::time::{Duration, SystemTime};
+
+use libp2p::core::ConnectedPoint;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::transport::transport_name;
+use crate::ui_api::UiUpdate;
+
+// Which side opened a connection, from its ConnectedPoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum PeerRole {
+    // We dialed the peer
+    Dialer,
+    // The peer dialed us
+    Listener,
+}
+
+impl fmt::Display for PeerRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerRole::Dialer => write!(f, "dialer"),
+            PeerRole::Listener => write!(f, "listener"),
+        }
+    }
+}
+
+// One open connection to a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerConnection {
+    // Remote address of the connection, for a listener this is the peer's ephemeral port
+    pub address: Multiaddr,
+    pub role: PeerRole,
+    // e.g. tcp or quic-v1
+    pub transport: String,
+    // yamux, mplex or quic
+    pub muxer: String,
+}
+
+impl PeerConnection {
+    fn new(endpoint: &ConnectedPoint, muxer: &str) -> Self {
+        let (role, address) = match endpoint {
+            ConnectedPoint::Dialer { address, .. } => (PeerRole::Dialer, address.clone()),
+            ConnectedPoint::Listener { send_back_addr, .. } => {
+                (PeerRole::Listener, send_back_addr.clone())
+            }
+        };
+        PeerConnection {
+            transport: transport_name(&address).to_string(),
+            muxer: muxer.to_string(),
+            address,
+            role,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub peer_id: PeerId,
+    // Address and role of the oldest open connection
+    pub address: Multiaddr,
+    pub role: PeerRole,
+    // Transports of the open connections, e.g. tcp or tcp,quic-v1
+    #[serde(default)]
+    pub transport: String,
+    // Stream multiplexers of the open connections, e.g. yamux or yamux,quic
+    #[serde(default)]
+    pub muxer: String,
+    pub connections: u32,
+    // Every open connection, oldest first
+    #[serde(default)]
+    pub links: Vec<PeerConnection>,
+    pub connected_since: SystemTime,
+    // Pubsub topics the peer announced
+    pub topics: Vec<String>,
+}
+
+impl PeerInfo {
+    pub fn connection_age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.connected_since)
+            .unwrap_or_default()
+    }
+
+    // Follows the open connections after one was opened or closed
+    fn links_changed(&mut self) {
+        if let Some(oldest) = self.links.first() {
+            self.address = oldest.address.clone();
+            self.role = oldest.role;
+        }
+        self.transport = distinct(self.links.iter().map(|l| l.transport.as_str()));
+        self.muxer = distinct(self.links.iter().map(|l| l.muxer.as_str()));
+    }
+}
+
+// Comma separated, each value once in order of first appearance
+fn distinct<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    let mut seen: Vec<&str> = Vec::new();
+    for value in values {
+        if !seen.contains(&value) {
+            seen.push(value);
+        }
+    }
+    seen.join(",")
+}
+
+#[derive(Debug, Default)]
+pub struct PeerTracker {
+    peers: HashMap<PeerId, PeerInfo>,
+}
+
+impl PeerTracker {
+    pub fn connection_established(
+        &mut self,
+        peer_id: PeerId,
+        endpoint: &ConnectedPoint,
+        num_established: NonZeroU32,
+        muxer: &str,
+    ) -> UiUpdate {
+        let link = PeerConnection::new(endpoint, muxer);
+        let peer = self.peers.entry(peer_id).or_insert_with(|| PeerInfo {
+            peer_id,
+            address: link.address.clone(),
+            role: link.role,
+            transport: String::new(),
+            muxer: String::new(),
+            connections: 0,
+            links: Vec::new(),
+            connected_since: SystemTime::now(),
+            topics: Vec::new(),
+        });
+        peer.links.push(link);
+        peer.links_changed();
+        peer.connections = num_established.get();
+        UiUpdate::PeerChanged(peer.clone())
+    }
+
+    pub fn connection_closed(
+        &mut self,
+        peer_id: PeerId,
+        endpoint: &ConnectedPoint,
+        num_established: u32,
+    ) -> UiUpdate {
+        match self.peers.get_mut(&peer_id) {
+            Some(peer) if num_established > 0 => {
+                let closed = PeerConnection::new(endpoint, "");
+                let index = peer
+                    .links
+                    .iter()
+                    .position(|l| l.role == closed.role && l.address == closed.address);
+                if let Some(index) = index {
+                    peer.links.remove(index);
+                    peer.links_changed();
+                }
+                peer.connections = num_established;
+                UiUpdate::PeerChanged(peer.clone())
+            }
+            _ => {
+                self.peers.remove(&peer_id);
+                UiUpdate::PeerGone(peer_id)
+            }
+        }
+    }
+
+    // Subscriptions of peers that are not connected any more are ignored
+    pub fn topic_subscribed(&mut self, peer_id: PeerId, topic: String) -> Option<UiUpdate> {
+        let peer = self.peers.get_mut(&peer_id)?;
+        if !peer.topics.contains(&topic) {
+            peer.topics.push(topic);
+        }
+        Some(UiUpdate::PeerChanged(peer.clone()))
+    }
+
+    pub fn topic_unsubscribed(&mut self, peer_id: PeerId, topic: &str) -> Option<UiUpdate> {
+        let peer = self.peers.get_mut(&peer_id)?;
+        peer.topics.retain(|t| t != topic);
+        Some(UiUpdate::PeerChanged(peer.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::core::Endpoint;
+
+    fn dialer(address: &str) -> ConnectedPoint {
+        ConnectedPoint::Dialer {
+            address: address.parse().unwrap(),
+            role_override: Endpoint::Dialer,
+        }
+    }
+
+    fn listener(send_back_addr: &str) -> ConnectedPoint {
+        ConnectedPoint::Listener {
+            local_addr: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+            send_back_addr: send_back_addr.parse().unwrap(),
+        }
+    }
+
+    fn changed(update: UiUpdate) -> PeerInfo {
+        match update {
+            UiUpdate::PeerChanged(peer) => peer,
+            other => panic!("expected PeerChanged, got {other:?}"),
+        }
+    }
+
+    fn established(n: u32) -> NonZeroU32 {
+        NonZeroU32::new(n).unwrap()
+    }
+
+    #[test]
+    fn every_open_connection_is_shown() {
+        let mut tracker = PeerTracker::default();
+        let peer_id = PeerId::random();
+        let tcp = dialer("/ip4/10.0.0.2/tcp/4001");
+        let quic = listener("/ip4/10.0.0.2/udp/50000/quic-v1");
+
+        tracker.connection_established(peer_id, &tcp, established(1), "yamux");
+        let peer = changed(tracker.connection_established(peer_id, &quic, established(2), "quic"));
+        assert_eq!(peer.transport, "tcp,quic-v1");
+        assert_eq!(peer.muxer, "yamux,quic");
+        assert_eq!(peer.links.len(), 2);
+        assert_eq!(peer.role, PeerRole::Dialer);
+
+        // the oldest remaining connection takes over address and role
+        let peer = changed(tracker.connection_closed(peer_id, &tcp, 1));
+        assert_eq!(peer.transport, "quic-v1");
+        assert_eq!(peer.muxer, "quic");
+        assert_eq!(peer.role, PeerRole::Listener);
+        assert_eq!(peer.address, "/ip4/10.0.0.2/udp/50000/quic-v1".parse::<Multiaddr>().unwrap());
+
+        assert!(matches!(
+            tracker.connection_closed(peer_id, &quic, 0),
+            UiUpdate::PeerGone(gone) if gone == peer_id
+        ));
+    }
+
+    #[test]
+    fn same_transport_is_named_once() {
+        let mut tracker = PeerTracker::default();
+        let peer_id = PeerId::random();
+        tracker.connection_established(
+            peer_id,
+            &listener("/ip4/10.0.0.3/tcp/50001"),
+            established(1),
+            "yamux",
+        );
+        let peer = changed(tracker.connection_established(
+            peer_id,
+            &listener("/ip4/10.0.0.3/tcp/50002"),
+            established(2),
+            "mplex",
+        ));
+        assert_eq!(peer.transport, "tcp");
+        assert_eq!(peer.muxer, "yamux,mplex");
+        assert_eq!(peer.connections, 2);
+    }
+}
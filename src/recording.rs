@@ -0,0 +1,126 @@
+// Recording of a scope session as JSON Lines for later replay.
+// The first line is a Start record with the PeerId of the node, every UiUpdate the network
+// loop sends follows as an Update record. Swarm events are recorded as UiUpdate::Event.
+// Each line is flushed as it is written so a recording survives a crash of the scope.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::ui_api::UiUpdate;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    Start {
+        time: SystemTime,
+        peer_id: PeerId,
+        version: String,
+    },
+    Update {
+        time: SystemTime,
+        // Monotonic time since the start of the recording
+        elapsed: Duration,
+        update: UiUpdate,
+    },
+}
+
+#[derive(Debug)]
+pub struct Recorder {
+    started: Instant,
+    // None after a write failed, the failure is reported once and recording stops
+    writer: RefCell<Option<LineWriter<File>>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, peer_id: PeerId) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path)
+            .map_err(|e| format!("Can not create recording {}: {e}", path.display()))?;
+        let recorder = Recorder {
+            started: Instant::now(),
+            writer: RefCell::new(Some(LineWriter::new(file))),
+        };
+        recorder.write(&Record::Start {
+            time: SystemTime::now(),
+            peer_id,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        })?;
+        Ok(recorder)
+    }
+
+    pub fn record(&self, update: &UiUpdate) -> Result<(), Box<dyn Error>> {
+        // Serialize by reference, the update itself goes on to the frontend
+        #[derive(Serialize)]
+        #[serde(tag = "kind", rename = "update")]
+        struct UpdateRef<'a> {
+            time: SystemTime,
+            elapsed: Duration,
+            update: &'a UiUpdate,
+        }
+        self.write(&UpdateRef {
+            time: SystemTime::now(),
+            elapsed: self.started.elapsed(),
+            update,
+        })
+    }
+
+    // Makes sure the recording is on disk before the scope exits
+    pub fn finish(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(file) = self.writer.borrow_mut().as_mut() {
+            file.flush()?;
+            file.get_ref().sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn write<T: Serialize>(&self, record: &T) -> Result<(), Box<dyn Error>> {
+        let mut writer = self.writer.borrow_mut();
+        let Some(file) = writer.as_mut() else {
+            return Ok(());
+        };
+        let result = serde_json::to_writer(&mut *file, record)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|()| Ok(file.write_all(b"\n")?));
+        if let Err(e) = result {
+            *writer = None;
+            return Err(format!("Recording stopped: {e}").into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    // The node that made the recording
+    pub peer_id: PeerId,
+    // Each update with the time since the recording started
+    pub updates: Vec<(Duration, UiUpdate)>,
+}
+
+pub fn read_recording(path: &Path) -> Result<Recording, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|e| format!("Can not open recording {}: {e}", path.display()))?;
+    let mut peer_id = None;
+    let mut updates = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str::<Record>(&line)
+            .map_err(|e| format!("{} line {}: {e}", path.display(), index + 1))?;
+        match record {
+            Record::Start { peer_id: id, .. } => peer_id = Some(id),
+            Record::Update { elapsed, update, .. } => updates.push((elapsed, update)),
+        }
+    }
+    let peer_id = peer_id
+        .ok_or_else(|| format!("{} is not a p2p-scope recording", path.display()))?;
+    Ok(Recording { peer_id, updates })
+}
@@ -0,0 +1,189 @@
+// Replay of a recording made with --record.
+// The frontends run as usual but their updates come from the recording instead of a swarm.
+// This loop stands in for the network loop: it answers the playback commands
+// and rejects everything that needs a live node.
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::frontend::{self, CommandSender, NodeInfo};
+use crate::identity_store::IdentitySource;
+use crate::recording::{read_recording, Recording};
+use crate::ui_api::{ReplayControl, UiCommand, UiUpdate};
+use crate::CliArguments;
+
+// Below this the wait between two updates no longer fits a Duration
+pub const MIN_SPEED: f64 = 0.01;
+
+// A playback speed for --speed and /speed, 0 or at least MIN_SPEED
+pub fn check_speed(speed: f64) -> Result<f64, String> {
+    if speed == 0.0 || (speed >= MIN_SPEED && speed.is_finite()) {
+        Ok(speed)
+    } else {
+        Err(format!("Invalid speed {speed}, give 0 or a factor of at least {MIN_SPEED}"))
+    }
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub(crate) struct ReplayCommand {
+    /// JSON Lines file written with --record.
+    file: PathBuf,
+    #[arg(long, default_value_t = 1.0)]
+    /// Playback speed, 2 plays twice as fast, at least 0.01. 0 plays without waiting.
+    speed: f64,
+    #[arg(long)]
+    /// Start paused, use /step or /resume in the message input to play.
+    paused: bool,
+}
+
+pub async fn run_replay(
+    replay: ReplayCommand,
+    clap_args: CliArguments,
+) -> Result<(), Box<dyn Error>> {
+    check_speed(replay.speed)?;
+    let Recording { peer_id, updates } = read_recording(&replay.file)?;
+    let update_count = updates.len();
+
+    let (command_sender, mut command_receiver) = CommandSender::channel(32);
+    let node_info = NodeInfo {
+        peer_id,
+        identity_source: IdentitySource::Recording(replay.file.clone()),
+        args: clap_args.clone(),
+    };
+    let mut frontends = frontend::frontends_for(&clap_args);
+    frontends.start(&node_info, command_sender).await?;
+    let send_ui_update = |update: UiUpdate| frontends.update(update);
+
+    let mut playback = Playback {
+        paused: replay.paused,
+        speed: replay.speed,
+        steps: 0,
+    };
+    send_ui_update(UiUpdate::TerminalOutput(format!(
+        "Replaying {} updates from {}, {}. Try /help for the playback commands.",
+        update_count,
+        replay.file.display(),
+        playback
+    )));
+
+    let mut pending = updates.into_iter().peekable();
+    let mut previous_elapsed = Duration::ZERO;
+    let mut last_sent = Instant::now();
+    let mut finished = false;
+    loop {
+        let due = match pending.peek() {
+            Some((elapsed, _)) if playback.playing() => {
+                let gap = elapsed.saturating_sub(previous_elapsed);
+                if playback.steps > 0 || playback.speed == 0.0 {
+                    Some(last_sent)
+                } else {
+                    // a gap too long to wait for pauses, /step or /resume go on from there
+                    let due = Duration::try_from_secs_f64(gap.as_secs_f64() / playback.speed)
+                        .ok()
+                        .and_then(|wait| last_sent.checked_add(wait));
+                    if due.is_none() {
+                        playback.paused = true;
+                        send_ui_update(UiUpdate::TerminalOutput(format!(
+                            "The next update is too far ahead at {}x speed, replay paused.",
+                            playback.speed
+                        )));
+                    }
+                    due
+                }
+            }
+            _ => None,
+        };
+        if pending.peek().is_none() && !finished {
+            finished = true;
+            send_ui_update(UiUpdate::TerminalOutput(
+                "Replay finished, Ctrl-C to quit.".to_string(),
+            ));
+        }
+
+        tokio::select! {
+            command = command_receiver.recv() => {
+                let Some((command, reply_sender)) =
+                    command.filter(|(c, _)| *c != UiCommand::Shutdown) else {
+                    frontends.stop().await;
+                    return Ok(());
+                };
+                let reply = match command {
+                    UiCommand::Replay(control) => {
+                        let checked = match control {
+                            ReplayControl::Speed(speed) => check_speed(speed).map(|_| control),
+                            _ => Ok(control),
+                        };
+                        checked.map(|control| {
+                            if control == ReplayControl::Resume {
+                                // do not rush through the time spent paused
+                                last_sent = Instant::now();
+                            }
+                            playback.apply(control);
+                            UiUpdate::CommandResult(format!("Replay {}", playback))
+                        })
+                    }
+                    other => Err(format!("Not available while replaying a recording: {:?}", other)),
+                };
+                send_ui_update(match &reply {
+                    Ok(update) => update.clone(),
+                    Err(e) => UiUpdate::CommandError(e.clone()),
+                });
+                if let Some(reply_sender) = reply_sender {
+                    let _ = reply_sender.send(reply.map(Some));
+                }
+            }
+            _ = tokio::time::sleep_until(due.unwrap_or(last_sent)), if due.is_some() => {
+                if let Some((elapsed, update)) = pending.next() {
+                    previous_elapsed = elapsed;
+                    last_sent = Instant::now();
+                    playback.steps = playback.steps.saturating_sub(1);
+                    send_ui_update(update);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Playback {
+    paused: bool,
+    speed: f64,
+    // Updates still to play while paused
+    steps: u32,
+}
+
+impl Playback {
+    fn playing(&self) -> bool {
+        !self.paused || self.steps > 0
+    }
+
+    fn apply(&mut self, control: ReplayControl) {
+        match control {
+            ReplayControl::Pause => self.paused = true,
+            ReplayControl::Resume => {
+                self.paused = false;
+                self.steps = 0;
+            }
+            ReplayControl::Step(count) => {
+                self.paused = true;
+                self.steps = self.steps.saturating_add(count);
+            }
+            ReplayControl::Speed(speed) => self.speed = speed,
+        }
+    }
+}
+
+impl std::fmt::Display for Playback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.paused {
+            write!(f, "paused")
+        } else if self.speed == 0.0 {
+            write!(f, "playing without waiting")
+        } else {
+            write!(f, "playing at {}x speed", self.speed)
+        }
+    }
+}
@@ -0,0 +1,227 @@
+// Runtime scope commands typed into the message input.
+// Lines starting with '/' are parsed here into a ScopeCommand, anything else is chat.
+// Parsing happens in the UI thread, the command is then sent to the tokio side
+// as a UiCommand so it goes through the same API as every other frontend.
+
+use std::str::FromStr;
+
+use libp2p::{Multiaddr, PeerId};
+
+use crate::replay::{check_speed, MIN_SPEED};
+use crate::ui_api::{ReplayControl, StateQuery, UiCommand};
+
+pub const HELP_TEXT: &str = "\
+Scope commands:
+  /dial <multiaddr>       connect to a peer
+  /stick <multiaddr>      connect to a peer and redial it whenever the connection is lost
+  /unstick <multiaddr>    stop redialing an address
+  /listen <multiaddr>     start listening on an address
+  /peers                  list connected peers
+  /listeners              list listening addresses
+  /topics                 list subscribed topics
+  /topic <topic>          send chat messages to a subscribed topic
+  /theme <light|dark|file> switch the colors, file is a cursive theme file
+  /subscribe <topic>      subscribe to a pubsub topic
+  /unsubscribe [topic]    unsubscribe, defaults to the current chat topic
+  /disconnect <peer id>   close all connections to a peer
+  /help                   show this help
+Replaying a recording:
+  /pause, /resume         pause or continue playback
+  /step [n]               pause and play the next n updates, default 1
+  /speed <factor>         playback speed, 2 is twice as fast, 0 does not wait";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeCommand {
+    Dial(Multiaddr),
+    Stick(Multiaddr),
+    Unstick(Multiaddr),
+    Listen(Multiaddr),
+    Peers,
+    Listeners,
+    Topics,
+    Topic(String),
+    Theme(String),
+    Subscribe(String),
+    Unsubscribe(Option<String>),
+    Disconnect(PeerId),
+    Replay(ReplayControl),
+    Help,
+}
+
+impl ScopeCommand {
+    // True for input the message box should treat as a command rather than chat
+    pub fn is_command(input: &str) -> bool {
+        input.trim_start().starts_with('/')
+    }
+
+    // The frontend API command that carries out this scope command.
+    // Help, Topic and Theme only change the frontend so they have none, neither has
+    // /unsubscribe without a topic when there is no chat topic.
+    pub fn into_ui_command(self, chat_topic: Option<&str>) -> Option<UiCommand> {
+        match self {
+            ScopeCommand::Dial(addr) => Some(UiCommand::Dial(addr)),
+            ScopeCommand::Stick(addr) => Some(UiCommand::Stick(addr)),
+            ScopeCommand::Unstick(addr) => Some(UiCommand::Unstick(addr)),
+            ScopeCommand::Listen(addr) => Some(UiCommand::Listen(addr)),
+            ScopeCommand::Peers => Some(UiCommand::Query(StateQuery::Peers)),
+            ScopeCommand::Listeners => Some(UiCommand::Query(StateQuery::Listeners)),
+            ScopeCommand::Topics => Some(UiCommand::Query(StateQuery::Topics)),
+            ScopeCommand::Subscribe(topic) => Some(UiCommand::Subscribe(topic)),
+            ScopeCommand::Unsubscribe(topic) => topic
+                .or_else(|| chat_topic.map(str::to_string))
+                .map(UiCommand::Unsubscribe),
+            ScopeCommand::Disconnect(peer_id) => Some(UiCommand::HangUp(peer_id)),
+            ScopeCommand::Replay(control) => Some(UiCommand::Replay(control)),
+            ScopeCommand::Topic(_) | ScopeCommand::Theme(_) | ScopeCommand::Help => None,
+        }
+    }
+}
+
+impl FromStr for ScopeCommand {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let Some(line) = input.strip_prefix('/') else {
+            return Err(format!("Not a command: {input}"));
+        };
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+        if let Some(extra) = words.next() {
+            return Err(format!("/{name}: unexpected argument '{extra}'"));
+        }
+        let required = |what: &str| argument.ok_or_else(|| format!("/{name} needs a {what}"));
+        let no_argument = |command: ScopeCommand| match argument {
+            Some(arg) => Err(format!("/{name} takes no argument, got '{arg}'")),
+            None => Ok(command),
+        };
+
+        match name {
+            "dial" => Ok(ScopeCommand::Dial(parse_multiaddr(required("multiaddr")?)?)),
+            "stick" => Ok(ScopeCommand::Stick(parse_multiaddr(required("multiaddr")?)?)),
+            "unstick" => Ok(ScopeCommand::Unstick(parse_multiaddr(required("multiaddr")?)?)),
+            "listen" => Ok(ScopeCommand::Listen(parse_multiaddr(required("multiaddr")?)?)),
+            "peers" => no_argument(ScopeCommand::Peers),
+            "listeners" => no_argument(ScopeCommand::Listeners),
+            "topics" => no_argument(ScopeCommand::Topics),
+            "topic" => Ok(ScopeCommand::Topic(required("topic")?.to_string())),
+            "theme" => Ok(ScopeCommand::Theme(required("theme")?.to_string())),
+            "subscribe" => Ok(ScopeCommand::Subscribe(required("topic")?.to_string())),
+            "unsubscribe" => Ok(ScopeCommand::Unsubscribe(argument.map(str::to_string))),
+            "disconnect" => {
+                let peer = required("peer id")?;
+                let peer_id = peer
+                    .parse::<PeerId>()
+                    .map_err(|e| format!("Invalid peer id '{peer}': {e}"))?;
+                Ok(ScopeCommand::Disconnect(peer_id))
+            }
+            "pause" => no_argument(ScopeCommand::Replay(ReplayControl::Pause)),
+            "resume" => no_argument(ScopeCommand::Replay(ReplayControl::Resume)),
+            "step" => {
+                let count = match argument {
+                    Some(n) => n.parse::<u32>().map_err(|e| format!("Invalid step count '{n}': {e}"))?,
+                    None => 1,
+                };
+                Ok(ScopeCommand::Replay(ReplayControl::Step(count)))
+            }
+            "speed" => {
+                let factor = required("factor")?;
+                let speed = factor.parse::<f64>().map_err(|_| {
+                    format!("Invalid speed '{factor}', give 0 or a factor of at least {MIN_SPEED}")
+                })?;
+                Ok(ScopeCommand::Replay(ReplayControl::Speed(check_speed(speed)?)))
+            }
+            "help" | "?" => no_argument(ScopeCommand::Help),
+            "" => Err("Empty command, try /help".to_string()),
+            unknown => Err(format!("Unknown command /{unknown}, try /help")),
+        }
+    }
+}
+
+fn parse_multiaddr(text: &str) -> Result<Multiaddr, String> {
+    text.parse()
+        .map_err(|e| format!("Invalid multiaddr '{text}': {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "/ip4/127.0.0.1/tcp/4001";
+    const PEER: &str = "12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X";
+
+    #[test]
+    fn parses_every_command() {
+        let addr: Multiaddr = ADDR.parse().unwrap();
+        let peer: PeerId = PEER.parse().unwrap();
+        let table = [
+            (format!("/dial {ADDR}"), ScopeCommand::Dial(addr.clone())),
+            (format!("/stick {ADDR}"), ScopeCommand::Stick(addr.clone())),
+            (format!("/unstick {ADDR}"), ScopeCommand::Unstick(addr.clone())),
+            (format!("/listen {ADDR}"), ScopeCommand::Listen(addr)),
+            ("/peers".to_string(), ScopeCommand::Peers),
+            ("/listeners".to_string(), ScopeCommand::Listeners),
+            ("/topics".to_string(), ScopeCommand::Topics),
+            ("/topic lab".to_string(), ScopeCommand::Topic("lab".to_string())),
+            ("/theme light".to_string(), ScopeCommand::Theme("light".to_string())),
+            ("/subscribe lab".to_string(), ScopeCommand::Subscribe("lab".to_string())),
+            ("/unsubscribe lab".to_string(), ScopeCommand::Unsubscribe(Some("lab".to_string()))),
+            ("/unsubscribe".to_string(), ScopeCommand::Unsubscribe(None)),
+            (format!("/disconnect {PEER}"), ScopeCommand::Disconnect(peer)),
+            ("/pause".to_string(), ScopeCommand::Replay(ReplayControl::Pause)),
+            ("/resume".to_string(), ScopeCommand::Replay(ReplayControl::Resume)),
+            ("/step".to_string(), ScopeCommand::Replay(ReplayControl::Step(1))),
+            ("/step 5".to_string(), ScopeCommand::Replay(ReplayControl::Step(5))),
+            ("/speed 2".to_string(), ScopeCommand::Replay(ReplayControl::Speed(2.0))),
+            ("/speed 0".to_string(), ScopeCommand::Replay(ReplayControl::Speed(0.0))),
+            ("/help".to_string(), ScopeCommand::Help),
+            ("/?".to_string(), ScopeCommand::Help),
+            ("  /peers  ".to_string(), ScopeCommand::Peers),
+        ];
+        for (input, expected) in table {
+            assert_eq!(input.parse::<ScopeCommand>(), Ok(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let table = [
+            // missing arguments
+            "/dial", "/stick", "/unstick", "/listen", "/topic", "/theme", "/subscribe",
+            "/disconnect", "/speed",
+            // extra arguments
+            "/peers now", "/listeners all", "/topics all", "/pause 1", "/resume 1", "/help me",
+            "/subscribe a b", "/dial /ip4/127.0.0.1/tcp/1 /ip4/127.0.0.1/tcp/2",
+            // invalid values
+            "/dial 127.0.0.1:4001", "/listen /ip4/256.0.0.1/tcp/1", "/stick /tcp/x",
+            "/disconnect not-a-peer", "/disconnect QmNotAPeer", "/step -1", "/step many",
+            "/speed fast", "/speed 0.001", "/speed -1",
+            // no command
+            "/", "/frobnicate",
+        ];
+        for input in table {
+            assert!(input.parse::<ScopeCommand>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn unsubscribe_defaults_to_the_chat_topic() {
+        let unsubscribe = |topic: Option<&str>, chat_topic| {
+            ScopeCommand::Unsubscribe(topic.map(str::to_string)).into_ui_command(chat_topic)
+        };
+        let lab = Some(UiCommand::Unsubscribe("lab".to_string()));
+        assert_eq!(unsubscribe(Some("lab"), None), lab);
+        assert_eq!(unsubscribe(None, Some("lab")), lab);
+        assert_eq!(unsubscribe(None, None), None);
+    }
+
+    #[test]
+    fn text_without_a_slash_is_chat() {
+        for input in ["hello", "hello /dial", " ", "", "peers"] {
+            assert!(!ScopeCommand::is_command(input), "{input}");
+            assert!(input.parse::<ScopeCommand>().is_err(), "{input}");
+        }
+        assert!(ScopeCommand::is_command("  /peers"));
+    }
+}
@@ -0,0 +1,294 @@
+// Structured, timestamped record of every swarm event for the event log view.
+// The network loop turns each SwarmEvent into a ScopeEvent before handling it so dial
+// failures and connection churn can be read back in order with the time between events.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use libp2p::core::ConnectedPoint;
+use libp2p::floodsub::FloodsubEvent;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{gossipsub, mdns, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::behaviour::AppBehaviourEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum EventCategory {
+    // Connections opening and closing, inbound handshakes
+    Connection,
+    // Outgoing dial attempts and their failures, DNS lookups of dialed names
+    Dial,
+    // Listening addresses and listener failures
+    Listener,
+    // Floodsub and gossipsub messages and subscriptions
+    Pubsub,
+    // mDNS discovery
+    Discovery,
+}
+
+impl EventCategory {
+    pub const ALL: [EventCategory; 5] = [
+        EventCategory::Connection,
+        EventCategory::Dial,
+        EventCategory::Listener,
+        EventCategory::Pubsub,
+        EventCategory::Discovery,
+    ];
+}
+
+impl fmt::Display for EventCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventCategory::Connection => write!(f, "connection"),
+            EventCategory::Dial => write!(f, "dial"),
+            EventCategory::Listener => write!(f, "listener"),
+            EventCategory::Pubsub => write!(f, "pubsub"),
+            EventCategory::Discovery => write!(f, "discovery"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeEvent {
+    pub timestamp: SystemTime,
+    // Monotonic time since the previous event, zero for the first one
+    pub delta: Duration,
+    pub category: EventCategory,
+    pub peer: Option<PeerId>,
+    // Connection number assigned by SwarmEventLog, see there
+    pub connection_id: Option<u64>,
+    pub summary: String,
+    // Debug formatting of the original SwarmEvent
+    pub raw: String,
+}
+
+// Turns swarm events into ScopeEvents.
+// libp2p-swarm 0.42 does not put a ConnectionId on SwarmEvent, so connections are numbered
+// here by peer and endpoint, which is unique for each open connection.
+#[derive(Debug, Default)]
+pub struct SwarmEventLog {
+    last_event: Option<Instant>,
+    next_connection_id: u64,
+    open_connections: HashMap<(PeerId, ConnectedPoint), u64>,
+}
+
+impl SwarmEventLog {
+    pub fn record<E: fmt::Debug + fmt::Display>(
+        &mut self,
+        event: &SwarmEvent<AppBehaviourEvent, E>,
+    ) -> ScopeEvent {
+        let delta = self.delta();
+        let connection_id = match event {
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                self.next_connection_id += 1;
+                self.open_connections
+                    .insert((*peer_id, endpoint.clone()), self.next_connection_id);
+                Some(self.next_connection_id)
+            }
+            SwarmEvent::ConnectionClosed { peer_id, endpoint, .. } => {
+                self.open_connections.remove(&(*peer_id, endpoint.clone()))
+            }
+            _ => None,
+        };
+        let (category, peer, summary) = describe(event);
+        ScopeEvent {
+            timestamp: SystemTime::now(),
+            delta,
+            category,
+            peer,
+            connection_id,
+            summary,
+            raw: format!("{:?}", event),
+        }
+    }
+
+    // Something the network loop learned outside of the swarm, like a DNS lookup
+    pub fn note(
+        &mut self,
+        category: EventCategory,
+        peer: Option<PeerId>,
+        summary: String,
+    ) -> ScopeEvent {
+        ScopeEvent {
+            timestamp: SystemTime::now(),
+            delta: self.delta(),
+            category,
+            peer,
+            connection_id: None,
+            raw: summary.clone(),
+            summary,
+        }
+    }
+
+    fn delta(&mut self) -> Duration {
+        let now = Instant::now();
+        let delta = self
+            .last_event
+            .map(|last| now.duration_since(last))
+            .unwrap_or_default();
+        self.last_event = Some(now);
+        delta
+    }
+}
+
+fn describe<E: fmt::Display>(
+    event: &SwarmEvent<AppBehaviourEvent, E>,
+) -> (EventCategory, Option<PeerId>, String) {
+    use EventCategory::*;
+    match event {
+        SwarmEvent::Behaviour(behaviour_event) => describe_behaviour(behaviour_event),
+        SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, established_in, .. } => (
+            Connection,
+            Some(*peer_id),
+            format!(
+                "Connected {} {} in {}ms, {} open",
+                direction(endpoint),
+                endpoint.get_remote_address(),
+                established_in.as_millis(),
+                num_established
+            ),
+        ),
+        SwarmEvent::ConnectionClosed { peer_id, endpoint, num_established, cause } => {
+            let cause = match cause {
+                Some(e) => e.to_string(),
+                None => "closed locally".to_string(),
+            };
+            (
+                Connection,
+                Some(*peer_id),
+                format!(
+                    "Closed {} {}: {}, {} open",
+                    direction(endpoint),
+                    endpoint.get_remote_address(),
+                    cause,
+                    num_established
+                ),
+            )
+        }
+        SwarmEvent::IncomingConnection { local_addr, send_back_addr } => (
+            Connection,
+            None,
+            format!("Incoming from {} on {}", send_back_addr, local_addr),
+        ),
+        SwarmEvent::IncomingConnectionError { send_back_addr, error, .. } => (
+            Connection,
+            None,
+            format!("Incoming from {} failed: {}", send_back_addr, error),
+        ),
+        SwarmEvent::OutgoingConnectionError { peer_id, error } => {
+            (Dial, *peer_id, format!("Dial failed: {}", error))
+        }
+        #[allow(deprecated)]
+        SwarmEvent::BannedPeer { peer_id, endpoint } => (
+            Connection,
+            Some(*peer_id),
+            format!("Banned peer at {}", endpoint.get_remote_address()),
+        ),
+        SwarmEvent::NewListenAddr { address, .. } => {
+            (Listener, None, format!("Listening on {}", address))
+        }
+        SwarmEvent::ExpiredListenAddr { address, .. } => {
+            (Listener, None, format!("Expired {}", address))
+        }
+        SwarmEvent::ListenerClosed { listener_id, reason, .. } => {
+            let summary = match reason {
+                Ok(()) => format!("Listener {:?} closed", listener_id),
+                Err(e) => format!("Listener {:?} closed: {}", listener_id, e),
+            };
+            (Listener, None, summary)
+        }
+        SwarmEvent::ListenerError { listener_id, error } => (
+            Listener,
+            None,
+            format!("Listener {:?} error: {}", listener_id, error),
+        ),
+        SwarmEvent::Dialing(peer_id) => (Dial, Some(*peer_id), "Dialing".to_string()),
+    }
+}
+
+fn describe_behaviour(event: &AppBehaviourEvent) -> (EventCategory, Option<PeerId>, String) {
+    use EventCategory::*;
+    match event {
+        AppBehaviourEvent::Floodsub(FloodsubEvent::Message(message)) => {
+            let topics: Vec<&str> = message.topics.iter().map(|t| t.id()).collect();
+            (
+                Pubsub,
+                Some(message.source),
+                format!("Message on {} ({})", topics.join(" "), payload_size(&message.data)),
+            )
+        }
+        AppBehaviourEvent::Floodsub(FloodsubEvent::Subscribed { peer_id, topic }) => {
+            (Pubsub, Some(*peer_id), format!("Subscribed to {}", topic.id()))
+        }
+        AppBehaviourEvent::Floodsub(FloodsubEvent::Unsubscribed { peer_id, topic }) => {
+            (Pubsub, Some(*peer_id), format!("Unsubscribed from {}", topic.id()))
+        }
+        AppBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+            propagation_source,
+            message_id,
+            message,
+        }) => (
+            Pubsub,
+            Some(message.source.unwrap_or(*propagation_source)),
+            format!(
+                "Message {} on {} ({}) via {}",
+                message_id,
+                message.topic,
+                payload_size(&message.data),
+                propagation_source
+            ),
+        ),
+        AppBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed { peer_id, topic }) => {
+            (Pubsub, Some(*peer_id), format!("Subscribed to {}", topic))
+        }
+        AppBehaviourEvent::Gossipsub(gossipsub::Event::Unsubscribed { peer_id, topic }) => {
+            (Pubsub, Some(*peer_id), format!("Unsubscribed from {}", topic))
+        }
+        AppBehaviourEvent::Gossipsub(gossipsub::Event::GossipsubNotSupported { peer_id }) => {
+            (Pubsub, Some(*peer_id), "Gossipsub not supported".to_string())
+        }
+        // the address lists are iterators, cloning them leaves the event intact for the loop
+        AppBehaviourEvent::Mdns(mdns::Event::Discovered(list)) => {
+            describe_mdns("Discovered", list.clone().collect())
+        }
+        AppBehaviourEvent::Mdns(mdns::Event::Expired(list)) => {
+            describe_mdns("Expired", list.clone().collect())
+        }
+    }
+}
+
+// Binary payloads are marked, the chat only shows a preview of them
+fn payload_size(data: &[u8]) -> String {
+    if std::str::from_utf8(data).is_ok() {
+        format!("{} bytes", data.len())
+    } else {
+        format!("{} bytes, not UTF-8", data.len())
+    }
+}
+
+// mDNS reports addresses in batches, they only get a peer when all are from the same one
+fn describe_mdns(
+    action: &str,
+    list: Vec<(PeerId, Multiaddr)>,
+) -> (EventCategory, Option<PeerId>, String) {
+    let peers: Vec<String> = list.iter().map(|(p, a)| format!("{} at {}", p, a)).collect();
+    let single_peer = list
+        .first()
+        .map(|(first, _)| *first)
+        .filter(|first| list.iter().all(|(p, _)| p == first));
+    (
+        EventCategory::Discovery,
+        single_peer,
+        format!("{} {}", action, peers.join(", ")),
+    )
+}
+
+fn direction(endpoint: &ConnectedPoint) -> &'static str {
+    if endpoint.is_dialer() {
+        "to"
+    } else {
+        "from"
+    }
+}
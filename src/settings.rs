@@ -0,0 +1,434 @@
+// Settings file, the layer between the built in defaults and the command line.
+// The file is TOML, by default <config dir>/p2p-scope/config.toml next to the identity key, or
+// the one given with --config. Every key is optional: a value given on the command line wins
+// over the file and the file wins over the defaults of the options. Relative paths in the file
+// are relative to its directory. `config dump` prints the result in the same format, with
+// absolute paths, so it can be used as a starting point.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use cursive::event::{Event, Key};
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+
+use crate::dns::DnsResolver;
+use crate::behaviour::{GossipsubMessageId, GossipsubValidation, PubsubMode};
+use crate::transport::{MuxerChoice, TransportKind};
+use crate::{CliArguments, ListenMode, Theme};
+
+const SETTINGS_FILE_NAME: &str = "config.toml";
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub(crate) enum ConfigCommand {
+    /// Print the effective configuration, the settings file merged with the command line,
+    /// in the format of the settings file.
+    Dump,
+    /// Print where the settings file is read from.
+    Path,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Settings {
+    identity: Option<PathBuf>,
+    record: Option<PathBuf>,
+    topics: Option<Vec<String>>,
+    listen_mode: Option<ListenMode>,
+    listen: Option<Vec<Multiaddr>>,
+    dial: Option<Vec<Multiaddr>>,
+    theme: Option<Theme>,
+    theme_file: Option<PathBuf>,
+    redial: RedialSettings,
+    pubsub: PubsubSettings,
+    transport: TransportSettings,
+    keybindings: Option<KeyBindings>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RedialSettings {
+    initial_ms: Option<u64>,
+    max_ms: Option<u64>,
+    jitter_percent: Option<u8>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct PubsubSettings {
+    protocol: Option<PubsubMode>,
+    gossipsub_heartbeat_ms: Option<u64>,
+    gossipsub_d: Option<usize>,
+    gossipsub_d_lo: Option<usize>,
+    gossipsub_d_hi: Option<usize>,
+    gossipsub_validation: Option<GossipsubValidation>,
+    gossipsub_message_id: Option<GossipsubMessageId>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct TransportSettings {
+    transports: Option<Vec<TransportKind>>,
+    muxer: Option<MuxerChoice>,
+    tcp_nodelay: Option<bool>,
+    ws_tls_cert: Option<PathBuf>,
+    ws_tls_key: Option<PathBuf>,
+    dns_resolver: Option<DnsResolver>,
+    swarm_key: Option<PathBuf>,
+}
+
+// TUI keys, written like "ctrl-c", "alt-q", "f10" or "esc". A plain character is not taken,
+// it would never reach the message input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct KeyBindings {
+    // Opens the quit dialog, pressed again it quits at once
+    pub quit: KeyBinding,
+    pub debug_console: KeyBinding,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            quit: KeyBinding::ctrl('c'),
+            debug_console: KeyBinding::ctrl('d'),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyBinding(Event);
+
+impl KeyBinding {
+    fn ctrl(c: char) -> Self {
+        KeyBinding(Event::CtrlChar(c))
+    }
+
+    pub fn event(&self) -> Event {
+        self.0.clone()
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let lower = name.to_lowercase();
+        let single = |rest: &str| {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        };
+        let event = if let Some(c) = lower.strip_prefix("ctrl-").and_then(single) {
+            Event::CtrlChar(c)
+        } else if let Some(c) = lower.strip_prefix("alt-").and_then(single) {
+            Event::AltChar(c)
+        } else if lower == "esc" {
+            Event::Key(Key::Esc)
+        } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Event::Key(function_key(n).ok_or_else(|| format!("No function key {name}"))?)
+        } else if single(&name).is_some() {
+            return Err(format!(
+                "{name} can not be bound, it would no longer type into the message input. \
+                 Use e.g. \"ctrl-{lower}\" or \"alt-{lower}\""
+            ));
+        } else {
+            return Err(format!(
+                "Unknown key {name}, use e.g. \"ctrl-c\", \"alt-q\", \"f10\" or \"esc\""
+            ));
+        };
+        Ok(KeyBinding(event))
+    }
+}
+
+impl From<KeyBinding> for String {
+    fn from(binding: KeyBinding) -> Self {
+        binding.to_string()
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Event::CtrlChar(c) => write!(f, "ctrl-{c}"),
+            Event::AltChar(c) => write!(f, "alt-{c}"),
+            Event::Key(Key::Esc) => write!(f, "esc"),
+            Event::Key(key) => match (1..=12).find(|&n| function_key(n) == Some(*key)) {
+                Some(n) => write!(f, "f{n}"),
+                None => write!(f, "{key:?}"),
+            },
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+fn function_key(n: u8) -> Option<Key> {
+    let keys = [
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6,
+        Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    ];
+    keys.get(usize::from(n).checked_sub(1)?).copied()
+}
+
+// Default settings file location, e.g. ~/.config/p2p-scope/config.toml on Linux.
+pub fn default_settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("p2p-scope").join(SETTINGS_FILE_NAME))
+}
+
+// Reads the settings file into the arguments. The default file may be missing, a file given
+// with --config must exist.
+pub fn apply_settings_file(
+    args: &mut CliArguments,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None => match default_settings_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        },
+    };
+    let settings = read_settings(&path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    settings
+        .apply(args, matches, dir)
+        .map_err(|e| format!("Invalid settings file {}: {e}", path.display()))?;
+    Ok(())
+}
+
+pub fn run_config(command: ConfigCommand, args: &CliArguments) -> Result<(), Box<dyn Error>> {
+    match command {
+        ConfigCommand::Dump => print!("{}", toml::to_string(&Settings::from_args(args))?),
+        ConfigCommand::Path => {
+            let path = args.config.clone().or_else(default_settings_path).ok_or(
+                "No default configuration directory for the settings file, use --config <path>",
+            )?;
+            let note = if path.exists() { "" } else { " (does not exist)" };
+            println!("{}{}", path.display(), note);
+        }
+    }
+    Ok(())
+}
+
+fn read_settings(path: &Path) -> Result<Settings, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!("Settings file {} does not exist", path.display()),
+        _ => format!("Can not read the settings file {}: {e}", path.display()),
+    })?;
+    Ok(toml::from_str(&text)
+        .map_err(|e| format!("Invalid settings file {}: {e}", path.display()))?)
+}
+
+impl Settings {
+    // The checks clap does for the command line. Relative paths are taken from dir, the
+    // directory of the file.
+    fn apply(
+        self,
+        args: &mut CliArguments,
+        matches: &ArgMatches,
+        dir: &Path,
+    ) -> Result<(), String> {
+        let layer = Layer(matches);
+        let path = |path: Option<PathBuf>| path.map(|path| Some(dir.join(path)));
+        // any identity option on the command line replaces the identity of the file
+        if !["ephemeral", "identity_seed"].iter().any(|id| layer.given(id)) {
+            layer.set("identity", &mut args.identity, path(self.identity));
+        }
+        layer.set("record", &mut args.record, path(self.record));
+        layer.set("topics", &mut args.topics, self.topics);
+        // listen addresses on the command line also replace the listen mode of the file
+        if !layer.given("listen") {
+            layer.set("listen_mode", &mut args.listen_mode, self.listen_mode.map(Some));
+        }
+        layer.set("listen", &mut args.listen, self.listen.map(Some));
+        layer.set("dial", &mut args.dial, self.dial.map(Some));
+        // a theme on the command line replaces either theme setting of the file
+        if !layer.given("theme_file") {
+            layer.set("theme", &mut args.theme, self.theme.map(Some));
+        }
+        if !layer.given("theme") {
+            layer.set("theme_file", &mut args.theme_file, path(self.theme_file));
+        }
+
+        let redial = &mut args.redial;
+        layer.set("initial_ms", &mut redial.initial_ms, self.redial.initial_ms);
+        layer.set("max_ms", &mut redial.max_ms, self.redial.max_ms);
+        layer.set("jitter_percent", &mut redial.jitter_percent, self.redial.jitter_percent);
+        redial.check()?;
+
+        let pubsub = self.pubsub;
+        let gossipsub = &mut args.gossipsub;
+        layer.set("pubsub", &mut args.pubsub, pubsub.protocol);
+        let heartbeat_ms = pubsub.gossipsub_heartbeat_ms.map(Some);
+        layer.set("heartbeat_ms", &mut gossipsub.heartbeat_ms, heartbeat_ms);
+        layer.set("mesh_n", &mut gossipsub.mesh_n, pubsub.gossipsub_d.map(Some));
+        layer.set("mesh_n_low", &mut gossipsub.mesh_n_low, pubsub.gossipsub_d_lo.map(Some));
+        layer.set("mesh_n_high", &mut gossipsub.mesh_n_high, pubsub.gossipsub_d_hi.map(Some));
+        layer.set("validation", &mut gossipsub.validation, pubsub.gossipsub_validation);
+        layer.set("message_id", &mut gossipsub.message_id, pubsub.gossipsub_message_id);
+
+        let transport = &mut args.transport;
+        layer.set("transports", &mut transport.transports, self.transport.transports);
+        layer.set("muxer", &mut transport.muxer, self.transport.muxer);
+        layer.set("tcp_nodelay", &mut transport.tcp_nodelay, self.transport.tcp_nodelay);
+        layer.set("ws_tls_cert", &mut transport.ws_tls_cert, path(self.transport.ws_tls_cert));
+        layer.set("ws_tls_key", &mut transport.ws_tls_key, path(self.transport.ws_tls_key));
+        layer.set("dns_resolver", &mut transport.dns_resolver, self.transport.dns_resolver);
+        layer.set("swarm_key", &mut transport.swarm_key, path(self.transport.swarm_key));
+
+        if let Some(keybindings) = self.keybindings {
+            args.keybindings = keybindings;
+        }
+        Ok(())
+    }
+
+    fn from_args(args: &CliArguments) -> Self {
+        Settings {
+            identity: absolute(&args.identity),
+            record: absolute(&args.record),
+            topics: Some(args.topics.clone()),
+            listen_mode: args.listen_mode.clone(),
+            listen: args.listen.clone(),
+            dial: args.dial.clone(),
+            theme: args.theme.clone(),
+            theme_file: absolute(&args.theme_file),
+            redial: RedialSettings {
+                initial_ms: Some(args.redial.initial_ms),
+                max_ms: Some(args.redial.max_ms),
+                jitter_percent: Some(args.redial.jitter_percent),
+            },
+            pubsub: PubsubSettings {
+                protocol: Some(args.pubsub),
+                gossipsub_heartbeat_ms: args.gossipsub.heartbeat_ms,
+                gossipsub_d: args.gossipsub.mesh_n,
+                gossipsub_d_lo: args.gossipsub.mesh_n_low,
+                gossipsub_d_hi: args.gossipsub.mesh_n_high,
+                gossipsub_validation: Some(args.gossipsub.validation),
+                gossipsub_message_id: Some(args.gossipsub.message_id),
+            },
+            transport: TransportSettings {
+                transports: Some(args.transport.transports.clone()),
+                muxer: Some(args.transport.muxer),
+                tcp_nodelay: Some(args.transport.tcp_nodelay),
+                ws_tls_cert: absolute(&args.transport.ws_tls_cert),
+                ws_tls_key: absolute(&args.transport.ws_tls_key),
+                dns_resolver: Some(args.transport.dns_resolver.clone()),
+                swarm_key: absolute(&args.transport.swarm_key),
+            },
+            keybindings: Some(args.keybindings.clone()),
+        }
+    }
+}
+
+// Paths of the command line are relative to the working directory, the dump may be read
+// from anywhere
+fn absolute(path: &Option<PathBuf>) -> Option<PathBuf> {
+    path.as_ref().map(|path| std::path::absolute(path).unwrap_or_else(|_| path.clone()))
+}
+
+// Which options were given on the command line
+struct Layer<'a>(&'a ArgMatches);
+
+impl Layer<'_> {
+    // The value from the file unless the option was on the command line
+    fn set<T>(&self, id: &str, target: &mut T, value: Option<T>) {
+        if let Some(value) = value {
+            if !self.given(id) {
+                *target = value;
+            }
+        }
+    }
+
+    fn given(&self, id: &str) -> bool {
+        self.0.value_source(id) == Some(ValueSource::CommandLine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    // The arguments of a command line with the settings file text on top
+    fn parse(command_line: &[&str], file: &str) -> Result<CliArguments, String> {
+        let command_line = std::iter::once("p2p-scope-rust").chain(command_line.iter().copied());
+        let matches = CliArguments::command()
+            .try_get_matches_from(command_line)
+            .map_err(|e| e.to_string())?;
+        let mut args = CliArguments::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+        let settings: Settings = toml::from_str(file).map_err(|e| e.to_string())?;
+        settings.apply(&mut args, &matches, Path::new("/etc/p2p-scope"))?;
+        Ok(args)
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let file = "topics = [\"lab\"]\n[redial]\nmax-ms = 10000\n[transport]\nmuxer = \"mplex\"\n";
+        let args = parse(&["--topic", "cli", "--redial-max", "5000", "--muxer", "yamux"], file)
+            .unwrap();
+        assert_eq!(args.topics, ["cli"]);
+        assert_eq!(args.redial.max_ms, 5000);
+        assert_eq!(args.transport.muxer, MuxerChoice::Yamux);
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let file = "topics = [\"lab\"]\n[redial]\nmax-ms = 10000\n[transport]\nmuxer = \"mplex\"\n";
+        let args = parse(&[], file).unwrap();
+        assert_eq!(args.topics, ["lab"]);
+        assert_eq!(args.redial.max_ms, 10000);
+        assert_eq!(args.transport.muxer, MuxerChoice::Mplex);
+    }
+
+    #[test]
+    fn relative_paths_are_taken_from_the_file_directory() {
+        let file = "identity = \"node.key\"\ntheme-file = \"/usr/share/theme.toml\"\n\
+            [transport]\nswarm-key = \"keys/lab.key\"\n";
+        let args = parse(&[], file).unwrap();
+        assert_eq!(args.identity, Some(PathBuf::from("/etc/p2p-scope/node.key")));
+        assert_eq!(args.theme_file, Some(PathBuf::from("/usr/share/theme.toml")));
+        assert_eq!(args.transport.swarm_key, Some(PathBuf::from("/etc/p2p-scope/keys/lab.key")));
+    }
+
+    #[test]
+    fn identity_options_on_the_command_line_replace_the_file_identity() {
+        let args = parse(&["--ephemeral"], "identity = \"node.key\"\n").unwrap();
+        assert_eq!(args.identity, None);
+        let args = parse(&["--identity", "mine.key"], "identity = \"node.key\"\n").unwrap();
+        assert_eq!(args.identity, Some(PathBuf::from("mine.key")));
+    }
+
+    #[test]
+    fn dump_round_trips() {
+        let command_line = [
+            "--topic", "a", "--topic", "b", "--listen-mode", "localhost", "--redial-max", "5000",
+            "--transport", "tcp,quic", "--dns-resolver", "quad9", "--swarm-key", "/tmp/lab.key",
+        ];
+        let file = "[keybindings]\nquit = \"ctrl-q\"\ndebug-console = \"f12\"\n";
+        let dump = toml::to_string(&Settings::from_args(&parse(&command_line, file).unwrap()))
+            .unwrap();
+        let reread = toml::to_string(&Settings::from_args(&parse(&[], &dump).unwrap())).unwrap();
+        assert_eq!(dump, reread);
+    }
+
+    #[test]
+    fn keybindings_need_a_key_that_does_not_type() {
+        let bind = |name: &str| KeyBinding::try_from(name.to_string());
+        assert_eq!(bind("ctrl-q"), Ok(KeyBinding(Event::CtrlChar('q'))));
+        assert_eq!(bind("Alt-x"), Ok(KeyBinding(Event::AltChar('x'))));
+        assert_eq!(bind("f10"), Ok(KeyBinding(Event::Key(Key::F10))));
+        assert_eq!(bind("esc"), Ok(KeyBinding(Event::Key(Key::Esc))));
+        assert!(bind("q").is_err());
+        assert!(bind("f13").is_err());
+        assert!(bind("ctrl-").is_err());
+        assert!(parse(&[], "[keybindings]\nquit = \"q\"\n").is_err());
+    }
+}
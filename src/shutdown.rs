@@ -0,0 +1,137 @@
+// Graceful shutdown of the network loop.
+// A Shutdown command does not stop the loop at once: the goodbye message goes out first, then
+// the listeners are closed and the peers disconnected. The loop keeps handling swarm events
+// until everything is closed or CLOSE_TIMEOUT passes, so the frontends and the recording see
+// the node go down.
+
+use std::io;
+use std::time::Duration;
+
+use libp2p::{PeerId, Swarm};
+use tokio::time::Instant;
+
+use crate::behaviour::AppBehaviour;
+use crate::frontend::CommandSender;
+use crate::listeners::ListenerTracker;
+use crate::ui_api::{UiCommand, UiUpdate};
+
+// Time for the goodbye message to reach the peers before they are disconnected
+const GOODBYE_GRACE: Duration = Duration::from_millis(500);
+// Longest wait for the listeners and connections to close
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+pub enum Shutdown {
+    // The goodbye message is on its way to the peers
+    Goodbye { until: Instant },
+    // Waiting for the listeners and connections to close
+    Closing { until: Instant },
+}
+
+impl Shutdown {
+    // Publishes the goodbye to every subscribed topic, without one closing starts right away
+    pub fn begin(
+        swarm: &mut Swarm<AppBehaviour>,
+        listener_tracker: &ListenerTracker,
+        topics: &[String],
+        goodbye: Option<&str>,
+    ) -> (Shutdown, Vec<UiUpdate>) {
+        let Some(goodbye) = goodbye else {
+            return close(swarm, listener_tracker);
+        };
+        let mut updates = Vec::new();
+        for topic in topics {
+            match swarm.behaviour_mut().publish(topic, goodbye.to_string()) {
+                Ok(()) => updates.push(UiUpdate::TerminalOutput(format!(
+                    "Shutting down, said goodbye to {topic}"))),
+                Err(e) => updates.push(UiUpdate::CommandError(format!(
+                    "Goodbye to {topic} failed: {e}"))),
+            }
+        }
+        let until = Instant::now() + GOODBYE_GRACE;
+        (Shutdown::Goodbye { until }, updates)
+    }
+
+    pub fn deadline(&self) -> Instant {
+        match self {
+            Shutdown::Goodbye { until } | Shutdown::Closing { until } => *until,
+        }
+    }
+
+    // Called at the deadline, None when the loop should stop
+    pub fn next(
+        &self,
+        swarm: &mut Swarm<AppBehaviour>,
+        listener_tracker: &ListenerTracker,
+    ) -> (Option<Shutdown>, Vec<UiUpdate>) {
+        match self {
+            Shutdown::Goodbye { .. } => {
+                let (closing, updates) = close(swarm, listener_tracker);
+                (Some(closing), updates)
+            }
+            Shutdown::Closing { .. } => {
+                let peers = swarm.network_info().num_peers();
+                let update = UiUpdate::CommandError(format!(
+                    "Stopped waiting for {peers} peers to disconnect"));
+                (None, vec![update])
+            }
+        }
+    }
+
+    // Everything is closed, checked after each swarm event
+    pub fn finished(&self, swarm: &Swarm<AppBehaviour>) -> bool {
+        matches!(self, Shutdown::Closing { .. })
+            && swarm.network_info().num_peers() == 0
+            && swarm.listeners().next().is_none()
+    }
+}
+
+fn close(
+    swarm: &mut Swarm<AppBehaviour>,
+    listener_tracker: &ListenerTracker,
+) -> (Shutdown, Vec<UiUpdate>) {
+    let listener_ids = listener_tracker.listener_ids();
+    for listener_id in &listener_ids {
+        swarm.remove_listener(*listener_id);
+    }
+    let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+    for peer_id in &peers {
+        let _ = swarm.disconnect_peer_id(*peer_id);
+    }
+    let update = UiUpdate::TerminalOutput(format!(
+        "Shutting down, closing {} listeners and disconnecting {} peers",
+        listener_ids.len(),
+        peers.len()
+    ));
+    let until = Instant::now() + CLOSE_TIMEOUT;
+    (Shutdown::Closing { until }, vec![update])
+}
+
+// Ctrl-C, and SIGTERM on unix, shut the node down like the Shutdown command.
+// A second one ends the process at once.
+pub fn spawn_signal_handler(commands: CommandSender) {
+    tokio::spawn(async move {
+        if wait_for_signal().await.is_err() {
+            return;
+        }
+        let _ = commands.send(UiCommand::Shutdown).await;
+        if wait_for_signal().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,221 @@
+// Pre-shared key of a private network.
+// With --swarm-key every TCP and WebSocket connection is encrypted with the key before noise,
+// so only nodes holding the same key get as far as the libp2p handshake. The file is the
+// swarm.key format of IPFS, a header line, /base16/ and 64 hex digits, so keys can be shared
+// with go-ipfs and other libp2p nodes of the same private network.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use libp2p::core::upgrade::NegotiationError;
+use libp2p::core::ConnectedPoint;
+use libp2p::pnet::{Fingerprint, PreSharedKey};
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use rand::RngCore;
+
+use crate::transport::HandshakeTimeout;
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum SwarmKeyCommand {
+    /// Generate a new random swarm key. Printed to stdout if no path is given.
+    Generate {
+        path: Option<PathBuf>,
+        #[arg(long)]
+        /// Replace the key file if it already exists. Nodes with the old key are locked out!
+        force: bool,
+    },
+    /// Print the fingerprint of a swarm key file, nodes with the same fingerprint share the key.
+    Fingerprint { path: PathBuf },
+}
+
+pub fn read_swarm_key(path: &Path) -> Result<PreSharedKey, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read swarm key file {}: {e}", path.display()))?;
+    Ok(text
+        .parse::<PreSharedKey>()
+        .map_err(|e| format!("Invalid swarm key file {}: {e}", path.display()))?)
+}
+
+// Remote addresses of connections that got through the private network handshake and are
+// not established yet, by the address the swarm reports for them. The peer is noted once
+// noise has told who it is.
+#[derive(Clone, Debug, Default)]
+pub struct PnetHandshakes(Arc<Mutex<HashMap<Multiaddr, Option<PeerId>>>>);
+
+impl PnetHandshakes {
+    pub fn passed(&self, endpoint: &ConnectedPoint) {
+        let address = endpoint.get_remote_address().clone();
+        self.0.lock().expect("pnet handshakes lock").insert(address, None);
+    }
+
+    pub fn authenticated(&self, peer_id: PeerId, endpoint: &ConnectedPoint) {
+        let mut handshakes = self.0.lock().expect("pnet handshakes lock");
+        if let Some(peer) = handshakes.get_mut(endpoint.get_remote_address()) {
+            *peer = Some(peer_id);
+        }
+    }
+
+    // Whether the connection got through the handshake, also forgets it
+    pub fn take(&self, address: &Multiaddr) -> bool {
+        self.0.lock().expect("pnet handshakes lock").remove(address).is_some()
+    }
+
+    // For failed dials that do not tell their addresses: the connections noise authenticated
+    // as the peer and the dials of /p2p/<peer> addresses
+    pub fn forget_peer(&self, peer_id: PeerId) {
+        self.0.lock().expect("pnet handshakes lock").retain(|address, peer| {
+            *peer != Some(peer_id) && dialed_peer(address) != Some(peer_id)
+        });
+    }
+}
+
+fn dialed_peer(address: &Multiaddr) -> Option<PeerId> {
+    match address.iter().last()? {
+        Protocol::P2p(hash) => PeerId::from_multihash(hash).ok(),
+        _ => None,
+    }
+}
+
+// A peer with another key, or without one, decrypts the protocol negotiation into garbage.
+// Multistream select then fails with a protocol error instead of a clean negotiation failure,
+// or both sides wait for more of the garbage until the handshake times out. Both can have
+// other causes, so this is a guess and only made for connections that got through the
+// private network handshake, see PnetHandshakes.
+pub fn key_mismatch(
+    fingerprint: Option<Fingerprint>,
+    through_pnet: bool,
+    error: &(dyn Error + 'static),
+) -> Option<String> {
+    let fingerprint = fingerprint.filter(|_| through_pnet)?;
+    let garbled = causes(error).any(|cause| {
+        matches!(cause.downcast_ref(), Some(NegotiationError::ProtocolError(_)))
+            || cause.is::<HandshakeTimeout>()
+    });
+    garbled.then(|| {
+        format!(
+            "maybe the peer is not in this private network and has another swarm key or none. \
+             Ours has the fingerprint {fingerprint}, compare it with `swarm-key fingerprint`"
+        )
+    })
+}
+
+// The error and the errors that caused it. An io::Error is followed into the error it wraps,
+// its source skips that one.
+fn causes<'a>(error: &'a (dyn Error + 'static)) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(Some(error), |&error| match error.downcast_ref::<io::Error>() {
+        Some(io_error) => io_error.get_ref().map(|inner| inner as &(dyn Error + 'static)),
+        None => error.source(),
+    })
+}
+
+// swarm-key subcommand, works on key files without starting the swarm.
+pub fn run_swarm_key(command: SwarmKeyCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        SwarmKeyCommand::Generate { path, force } => {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let key = PreSharedKey::new(bytes);
+            match path {
+                Some(path) => {
+                    write_swarm_key(&path, &key, force)?;
+                    println!("Created {}", path.display());
+                    println!("Fingerprint: {}", key.fingerprint());
+                }
+                None => print!("{key}"),
+            }
+        }
+        SwarmKeyCommand::Fingerprint { path } => {
+            println!("{}", read_swarm_key(&path)?.fingerprint());
+        }
+    }
+    Ok(())
+}
+
+// Like the identity file only the owner can read it, anyone with the key can join
+fn write_swarm_key(path: &Path, key: &PreSharedKey, overwrite: bool) -> Result<(), Box<dyn Error>> {
+    if !overwrite && path.exists() {
+        return Err(format!("Swarm key file {} already exists", path.display()).into());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Unable to write swarm key file {}: {e}", path.display()))?;
+    file.write_all(key.to_string().as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::core::transport::upgrade::TransportUpgradeError;
+    use libp2p::core::upgrade::{ProtocolError, UpgradeError};
+    use libp2p::core::Endpoint;
+
+    fn fingerprint() -> Option<Fingerprint> {
+        Some(PreSharedKey::new([7; 32]).fingerprint())
+    }
+
+    // Nested the way the swarm hands out a failed upgrade of a boxed transport
+    fn upgrade_error(error: NegotiationError) -> io::Error {
+        let error: TransportUpgradeError<io::Error, io::Error> =
+            TransportUpgradeError::Upgrade(UpgradeError::Select(error));
+        io::Error::other(io::Error::other(error))
+    }
+
+    #[test]
+    fn garbled_negotiation_hints_at_the_key() {
+        let garbled = upgrade_error(NegotiationError::ProtocolError(ProtocolError::InvalidMessage));
+        let hint = key_mismatch(fingerprint(), true, &garbled).unwrap();
+        assert!(hint.contains(&fingerprint().unwrap().to_string()));
+        let timeout = io::Error::other(io::Error::new(io::ErrorKind::TimedOut, HandshakeTimeout));
+        assert!(key_mismatch(fingerprint(), true, &timeout).is_some());
+    }
+
+    #[test]
+    fn other_failures_give_no_hint() {
+        let garbled = upgrade_error(NegotiationError::ProtocolError(ProtocolError::InvalidMessage));
+        // before the private network handshake or without a swarm key
+        assert!(key_mismatch(fingerprint(), false, &garbled).is_none());
+        assert!(key_mismatch(None, true, &garbled).is_none());
+        let unsupported = upgrade_error(NegotiationError::Failed);
+        assert!(key_mismatch(fingerprint(), true, &unsupported).is_none());
+        let refused = io::Error::other(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(key_mismatch(fingerprint(), true, &refused).is_none());
+    }
+
+    #[test]
+    fn failed_dials_of_a_peer_are_forgotten() {
+        let peer = PeerId::random();
+        let dialer = |address: Multiaddr| ConnectedPoint::Dialer {
+            address,
+            role_override: Endpoint::Dialer,
+        };
+        let by_p2p: Multiaddr = format!("/ip4/127.0.0.1/tcp/1/p2p/{peer}").parse().unwrap();
+        let by_noise: Multiaddr = "/ip4/127.0.0.1/tcp/2".parse().unwrap();
+        let other: Multiaddr = "/ip4/127.0.0.1/tcp/3".parse().unwrap();
+        let handshakes = PnetHandshakes::default();
+        for address in [&by_p2p, &by_noise, &other] {
+            handshakes.passed(&dialer(address.clone()));
+        }
+        handshakes.authenticated(peer, &dialer(by_noise.clone()));
+        handshakes.forget_peer(peer);
+        assert!(!handshakes.take(&by_p2p));
+        assert!(!handshakes.take(&by_noise));
+        assert!(handshakes.take(&other));
+        assert!(!handshakes.take(&other));
+    }
+}
@@ -0,0 +1,388 @@
+// The libp2p transports of the node.
+// TCP and WebSockets over TCP, upgraded with noise for authenticated encryption and yamux or
+// mplex to multiplex substreams on a connection, and QUIC which brings its own encryption and
+// streams. --transport picks which of them run, a dial goes to whichever understands the
+// address. With --muxer both the two sides negotiate the muxer, yamux first.
+// With --swarm-key TCP and WebSocket connections are a private network, see swarm_key.rs.
+// Each of them sits on a Dns layer so /dns4/<host>/... addresses can be dialed, under
+// WebSockets so a /wss dial still knows the host name for TLS, see dns.rs.
+// The muxer is hidden once a connection is boxed so the upgrade notes it in NegotiatedMuxers,
+// where the network loop picks it up when the connection is established.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::timeout::TransportTimeoutError;
+use libp2p::core::transport::{Boxed, OrTransport};
+use libp2p::core::upgrade::{self, SelectUpgrade};
+use libp2p::core::ConnectedPoint;
+use libp2p::futures::future::Either;
+use libp2p::futures::{AsyncRead, AsyncWrite};
+use libp2p::multiaddr::Protocol;
+use libp2p::websocket::{tls, WsConfig};
+use libp2p::pnet::{PnetConfig, PnetError, PreSharedKey};
+use libp2p::{identity, noise, tcp, yamux, Multiaddr, PeerId, Transport};
+use rustls_pemfile::Item;
+use serde::{Deserialize, Serialize};
+
+use crate::dns::{DnsLayers, DnsResolver};
+use crate::swarm_key::{read_swarm_key, PnetHandshakes};
+
+// Connecting, noise and the muxer together. Also ends connections stuck on a private network
+// handshake with a peer that has another swarm key.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(20);
+
+// The error of a connection that ran into HANDSHAKE_TIMEOUT
+#[derive(Debug)]
+pub struct HandshakeTimeout;
+
+impl fmt::Display for HandshakeTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "handshake timed out after {}s", HANDSHAKE_TIMEOUT.as_secs())
+    }
+}
+
+impl Error for HandshakeTimeout {}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransportKind {
+    Tcp,
+    // Addresses like /ip4/127.0.0.1/udp/4001/quic-v1
+    Quic,
+    // WebSockets over TCP, /tcp/<port>/ws and /tcp/<port>/wss, as spoken by browser nodes
+    Ws,
+}
+
+// Stream multiplexers offered on TCP connections
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MuxerChoice {
+    Yamux,
+    // Deprecated in much of the ecosystem, for older nodes
+    Mplex,
+    // Negotiated with the remote, yamux preferred
+    #[default]
+    Both,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct TransportOptions {
+    #[arg(long = "transport", value_name = "TRANSPORTS", value_enum, value_delimiter = ',',
+        default_value = "tcp")]
+    /// Transports to listen and dial with, e.g. tcp,quic,ws.
+    /// QUIC listens on /udp/<port>/quic-v1 addresses, ws on /tcp/<port>/ws.
+    pub transports: Vec<TransportKind>,
+    #[arg(long, value_enum, default_value_t)]
+    /// Stream multiplexer for TCP and WebSocket connections, both lets the remote pick.
+    pub muxer: MuxerChoice,
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    /// Send small writes on TCP connections right away instead of batching them.
+    pub tcp_nodelay: bool,
+    #[arg(long, value_name = "FILE", requires = "ws_tls_key")]
+    /// PEM certificate chain for listening on /wss addresses with the ws transport.
+    /// Dials to /wss addresses trust it too, so nodes sharing a local certificate can connect.
+    pub ws_tls_cert: Option<PathBuf>,
+    #[arg(long, value_name = "FILE", requires = "ws_tls_cert")]
+    /// PEM private key of the --ws-tls-cert certificate.
+    pub ws_tls_key: Option<PathBuf>,
+    #[arg(long, value_name = "RESOLVER", default_value_t)]
+    /// Name server for dialing /dns addresses: system, cloudflare, google, quad9
+    /// or the address of a name server, e.g. 192.0.2.53:53.
+    pub dns_resolver: DnsResolver,
+    #[arg(long, value_name = "FILE")]
+    /// Only connect to nodes with the same pre-shared key, in the swarm.key format of IPFS.
+    /// Create one with `swarm-key generate`. Not available with QUIC.
+    pub swarm_key: Option<PathBuf>,
+}
+
+impl Default for TransportOptions {
+    fn default() -> Self {
+        TransportOptions {
+            transports: vec![TransportKind::Tcp],
+            muxer: MuxerChoice::default(),
+            tcp_nodelay: true,
+            ws_tls_cert: None,
+            ws_tls_key: None,
+            dns_resolver: DnsResolver::default(),
+            swarm_key: None,
+        }
+    }
+}
+
+impl TransportOptions {
+    fn uses(&self, kind: TransportKind) -> bool {
+        self.transports.contains(&kind)
+    }
+
+    // An address on every transport for listening on ip with a port the OS assigns
+    pub fn listen_addrs(&self, ip: Ipv4Addr) -> Vec<Multiaddr> {
+        let mut addrs = Vec::new();
+        if self.uses(TransportKind::Tcp) {
+            addrs.push(Multiaddr::from(ip).with(Protocol::Tcp(0)));
+        }
+        if self.uses(TransportKind::Quic) {
+            addrs.push(Multiaddr::from(ip).with(Protocol::Udp(0)).with(Protocol::QuicV1));
+        }
+        if self.uses(TransportKind::Ws) {
+            let ws = Multiaddr::from(ip).with(Protocol::Tcp(0));
+            if self.ws_tls_cert.is_some() {
+                addrs.push(ws.clone().with(Protocol::Wss("/".into())));
+            }
+            addrs.push(ws.with(Protocol::Ws("/".into())));
+        }
+        addrs
+    }
+}
+
+// The muxer of each connection between the upgrade and ConnectionEstablished,
+// by peer and remote address
+#[derive(Clone, Debug, Default)]
+pub struct NegotiatedMuxers(Arc<Mutex<HashMap<(PeerId, Multiaddr), &'static str>>>);
+
+impl NegotiatedMuxers {
+    fn record(&self, peer_id: PeerId, endpoint: &ConnectedPoint, muxer: &'static str) {
+        let key = (peer_id, endpoint.get_remote_address().clone());
+        self.0.lock().expect("muxers lock").insert(key, muxer);
+    }
+
+    pub fn take(&self, peer_id: PeerId, endpoint: &ConnectedPoint) -> Option<&'static str> {
+        let key = (peer_id, endpoint.get_remote_address().clone());
+        self.0.lock().expect("muxers lock").remove(&key)
+    }
+
+    // Connections that failed after the upgrade never get established, the error tells the
+    // peer, the addresses or both. Without either nothing is removed.
+    pub fn forget(&self, peer_id: Option<PeerId>, addresses: &[&Multiaddr]) {
+        if peer_id.is_none() && addresses.is_empty() {
+            return;
+        }
+        self.0.lock().expect("muxers lock").retain(|(peer, address), _| {
+            !(peer_id.is_none_or(|p| p == *peer)
+                && (addresses.is_empty() || addresses.contains(&address)))
+        });
+    }
+}
+
+pub fn build_transport(
+    id_keys: &identity::Keypair,
+    options: &TransportOptions,
+    muxers: &NegotiatedMuxers,
+    dns: &DnsLayers,
+    handshakes: &PnetHandshakes,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn Error>> {
+    let psk = options.swarm_key.as_deref().map(read_swarm_key).transpose()?;
+    if psk.is_some() && options.uses(TransportKind::Quic) {
+        return Err("QUIC can not join a private network, drop quic from --transport \
+            or leave out --swarm-key"
+            .into());
+    }
+    let tcp_transport = |accepts| {
+        let tcp = tcp::tokio::Transport::new(tcp::Config::default().nodelay(options.tcp_nodelay));
+        dns.wrap(tcp, accepts)
+    };
+    // WebSockets only pass on the /tcp part of their addresses
+    let plain_tcp = |address: &Multiaddr| transport_name(address) == "tcp";
+    let ws = if options.uses(TransportKind::Ws) {
+        let mut ws = WsConfig::new(tcp_transport(|_| true)?);
+        if let Some(tls_config) = websocket_tls(options)? {
+            ws.set_tls_config(tls_config);
+        }
+        Some(ws)
+    } else {
+        None
+    };
+    // TCP does not take /ws addresses so WebSockets are asked first
+    let tcp = match (options.uses(TransportKind::Tcp), ws) {
+        (true, Some(ws)) => Some(upgrade_stream(
+            OrTransport::new(ws, tcp_transport(plain_tcp)?),
+            psk,
+            handshakes,
+            id_keys,
+            options.muxer,
+            muxers,
+        )?),
+        (true, None) => {
+            let tcp = tcp_transport(plain_tcp)?;
+            Some(upgrade_stream(tcp, psk, handshakes, id_keys, options.muxer, muxers)?)
+        }
+        (false, Some(ws)) => {
+            Some(upgrade_stream(ws, psk, handshakes, id_keys, options.muxer, muxers)?)
+        }
+        (false, None) => None,
+    };
+    let quic = if options.uses(TransportKind::Quic) {
+        let muxers = muxers.clone();
+        let quic = libp2p_quic::tokio::Transport::new(libp2p_quic::Config::new(id_keys));
+        let quic_only = |address: &Multiaddr| matches!(transport_name(address), "quic-v1" | "quic");
+        // the muxer is recorded outside of the Dns layer, by the address that was dialed
+        let quic = dns.wrap(quic, quic_only)?.map(move |(peer_id, connection), endpoint| {
+            // QUIC streams need no muxer of their own
+            muxers.record(peer_id, &endpoint, "quic");
+            (peer_id, StreamMuxerBox::new(connection))
+        });
+        Some(quic.boxed())
+    } else {
+        None
+    };
+    match (tcp, quic) {
+        (Some(tcp), Some(quic)) => Ok(OrTransport::new(quic, tcp)
+            .map(|either, _| either.into_inner())
+            .map_err(|error| error.into_inner())
+            .boxed()),
+        (Some(transport), None) | (None, Some(transport)) => Ok(transport),
+        (None, None) => Err("No transport selected, use e.g. --transport tcp".into()),
+    }
+}
+
+// The private network, noise and the stream multiplexer on top of a transport of plain
+// byte streams
+fn upgrade_stream<T>(
+    transport: T,
+    psk: Option<PreSharedKey>,
+    handshakes: &PnetHandshakes,
+    id_keys: &identity::Keypair,
+    muxer: MuxerChoice,
+    muxers: &NegotiatedMuxers,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn Error>>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Error + Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let noise = noise::Config::new(id_keys)
+        .map_err(|e| format!("Signing libp2p-noise static DH keypair failed: {e}"))?;
+    let passed = handshakes.clone();
+    let transport = transport.and_then(move |socket, endpoint| {
+        let handshakes = passed.clone();
+        async move {
+            match psk {
+                Some(psk) => {
+                    let socket = PnetConfig::new(psk).handshake(socket).await?;
+                    handshakes.passed(&endpoint);
+                    Ok::<_, PnetError>(Either::Left(socket))
+                }
+                None => Ok(Either::Right(socket)),
+            }
+        }
+    });
+    let authenticated = transport.upgrade(upgrade::Version::V1).authenticate(noise);
+    let muxers = muxers.clone();
+    let handshakes = handshakes.clone();
+    // the upgrade is through, note the muxer and whom the private network handshake was with
+    let upgraded = move |peer_id, endpoint: &ConnectedPoint, muxer| {
+        muxers.record(peer_id, endpoint, muxer);
+        handshakes.authenticated(peer_id, endpoint);
+    };
+    Ok(match muxer {
+        MuxerChoice::Yamux => authenticated
+            .multiplex(yamux::Config::default())
+            .timeout(HANDSHAKE_TIMEOUT)
+            .map_err(handshake_timeout)
+            .map(move |(peer_id, muxer), endpoint| {
+                upgraded(peer_id, &endpoint, "yamux");
+                (peer_id, StreamMuxerBox::new(muxer))
+            })
+            .boxed(),
+        MuxerChoice::Mplex => authenticated
+            .multiplex(libp2p_mplex::MplexConfig::new())
+            .timeout(HANDSHAKE_TIMEOUT)
+            .map_err(handshake_timeout)
+            .map(move |(peer_id, muxer), endpoint| {
+                upgraded(peer_id, &endpoint, "mplex");
+                (peer_id, StreamMuxerBox::new(muxer))
+            })
+            .boxed(),
+        MuxerChoice::Both => authenticated
+            .multiplex(SelectUpgrade::new(
+                yamux::Config::default(),
+                libp2p_mplex::MplexConfig::new(),
+            ))
+            .timeout(HANDSHAKE_TIMEOUT)
+            .map_err(handshake_timeout)
+            .map(move |(peer_id, muxer), endpoint| {
+                let name = match &muxer {
+                    Either::Left(_) => "yamux",
+                    Either::Right(_) => "mplex",
+                };
+                upgraded(peer_id, &endpoint, name);
+                (peer_id, StreamMuxerBox::new(muxer))
+            })
+            .boxed(),
+    })
+}
+
+// A timeout keeps its own type so a key mismatch can be told from other failures
+fn handshake_timeout<E: Error + Send + Sync + 'static>(error: TransportTimeoutError<E>) -> io::Error {
+    match error {
+        TransportTimeoutError::Timeout => io::Error::new(io::ErrorKind::TimedOut, HandshakeTimeout),
+        error => io::Error::other(error),
+    }
+}
+
+// Server side TLS for /wss from --ws-tls-cert and --ws-tls-key, without them /wss can only
+// be dialed, checked against the usual web roots
+fn websocket_tls(options: &TransportOptions) -> Result<Option<tls::Config>, Box<dyn Error>> {
+    let (cert_path, key_path) = match (&options.ws_tls_cert, &options.ws_tls_key) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        _ => return Err("ws-tls-cert and ws-tls-key must be given together".into()),
+    };
+    let certs: Vec<tls::Certificate> = read_pem(cert_path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(tls::Certificate::new(der)),
+            _ => None,
+        })
+        .collect();
+    if certs.is_empty() {
+        return Err(format!("No certificate in {}", cert_path.display()).into());
+    }
+    let key = read_pem(key_path)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(der),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key in {}", key_path.display()))?;
+    let mut builder = tls::Config::builder();
+    builder
+        .server(tls::PrivateKey::new(key), certs.clone())
+        .map_err(|e| format!("Can not use {} for /wss: {e}", cert_path.display()))?;
+    for cert in &certs {
+        builder.add_trust(cert)?;
+    }
+    Ok(Some(builder.finish()))
+}
+
+fn read_pem(path: &Path) -> Result<Vec<Item>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Can not read {}: {e}", path.display()))?;
+    Ok(rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| format!("Invalid PEM file {}: {e}", path.display()))?)
+}
+
+// Which transport a connection to the address runs on, for the peers view
+pub fn transport_name(address: &Multiaddr) -> &'static str {
+    address
+        .iter()
+        .filter_map(|protocol| match protocol {
+            Protocol::QuicV1 => Some("quic-v1"),
+            Protocol::Quic => Some("quic"),
+            Protocol::Tcp(_) => Some("tcp"),
+            Protocol::Ws(_) => Some("ws"),
+            Protocol::Wss(_) => Some("wss"),
+            _ => None,
+        })
+        .last()
+        .unwrap_or("unknown")
+}
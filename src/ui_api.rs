@@ -0,0 +1,108 @@
+// Implementation independent UI message types.
+// This is the facade between the swarm and any frontend, see frontend.rs for the frontends.
+// UiUpdate flows from the tokio network loop to the frontend and UiCommand flows back,
+// a frontend never touches the swarm directly.
+
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::dial_targets::DialTargetInfo;
+use crate::listeners::ListenAddrInfo;
+use crate::peers::PeerInfo;
+use crate::scope_event::ScopeEvent;
+
+// The chat topic joined when no --topic is given
+pub const DEFAULT_TOPIC: &str = "monolith";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)] // variants reserved for the facade API
+pub enum UiUpdate {
+    // A swarm event for the event log, timestamped with the time since the previous one
+    Event(ScopeEvent),
+    TextMessage(String, PeerId, String), //Topic, PeerID, Message
+    InputMessage(String),                // MessageText
+    // arbitrary program output to output_view
+    TerminalOutput(String),
+    AppendToView(ViewSpec, String),
+    ReplaceViewContent(ViewSpec, String),
+    // A peer connected or its connections or topics changed
+    PeerChanged(PeerInfo),
+    // The last connection to a peer closed
+    PeerGone(PeerId),
+    // A listening address was added, expired, closed or its listener reported an error
+    ListenAddrChanged(ListenAddrInfo),
+    // A sticky dial target was added, dialed, connected or is waiting to be redialed
+    DialTargetChanged(DialTargetInfo),
+    DialTargetRemoved(Multiaddr),
+    // The node joined or left a pubsub topic
+    Subscribed(String),
+    Unsubscribed(String),
+    // Outcome of a UiCommand
+    CommandResult(String),
+    CommandError(String),
+    // Replies to UiCommand::Query
+    PeerList(Vec<PeerId>),
+    ListenerList(Vec<Multiaddr>),
+    TopicList(Vec<String>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub enum ViewSpec {
+    ViewName(String),
+    ViewIdS(String),
+    ViewIdI(i32),
+}
+
+/// Requests from a frontend to the network loop.
+/// Every command is answered with a UiUpdate, `CommandResult` or `CommandError` unless noted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UiCommand {
+    /// Publish a chat message to a pubsub topic.
+    /// Only failures are reported, the frontend shows its own message when it is sent.
+    Publish { topic: String, message: String },
+    /// Dial a peer at a multiaddr.
+    Dial(Multiaddr),
+    /// Dial a multiaddr and dial it again whenever the dial fails or the connection closes,
+    /// answered with UiUpdate::DialTargetChanged.
+    Stick(Multiaddr),
+    /// Stop redialing a multiaddr, answered with UiUpdate::DialTargetRemoved.
+    /// Its connections stay open.
+    Unstick(Multiaddr),
+    /// Start listening on a multiaddr.
+    Listen(Multiaddr),
+    /// Close all connections to a peer.
+    HangUp(PeerId),
+    /// Join a pubsub topic, answered with UiUpdate::Subscribed on success.
+    Subscribe(String),
+    /// Leave a pubsub topic, answered with UiUpdate::Unsubscribed on success.
+    Unsubscribe(String),
+    /// Stop the network loop, no reply is sent.
+    Shutdown,
+    /// Ask for a snapshot of the node state.
+    Query(StateQuery),
+    /// Control playback of a recording, only `replay` accepts it.
+    Replay(ReplayControl),
+}
+
+/// The state a frontend can ask for with UiCommand::Query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateQuery {
+    /// Answered with UiUpdate::PeerList
+    Peers,
+    /// Answered with UiUpdate::ListenerList
+    Listeners,
+    /// Subscribed pubsub topics, answered with UiUpdate::TopicList
+    Topics,
+}
+
+/// Playback controls of `replay`, answered with the new playback state as UiUpdate::CommandResult.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReplayControl {
+    Pause,
+    Resume,
+    /// Pause and play the next n updates
+    Step(u32),
+    /// Playback speed, 2.0 plays twice as fast, 0 does not wait between updates
+    Speed(f64),
+}
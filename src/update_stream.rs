@@ -0,0 +1,133 @@
+// The UiUpdate stream for frontends with several clients, the control socket and the web
+// dashboard. A client that joins late first gets the state of the node as the updates that
+// lead to it, then every update after it.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use libp2p::PeerId;
+use tokio::sync::broadcast;
+
+use crate::dial_targets::DialTargetInfo;
+use crate::listeners::ListenAddrInfo;
+use crate::peers::PeerInfo;
+use crate::scope_event::ScopeEvent;
+use crate::ui_api::UiUpdate;
+
+// Updates a client may fall behind before it is told it missed some
+const STREAM_BUFFER: usize = 1024;
+// Events a client gets to catch up
+const STATE_EVENTS: usize = 1000;
+
+// The state is updated and the update sent under one lock so a new client gets the state and
+// then every update after it without gaps.
+#[derive(Debug, Clone)]
+pub struct UpdateStream {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: NodeState,
+    updates: broadcast::Sender<UiUpdate>,
+}
+
+impl Default for UpdateStream {
+    fn default() -> Self {
+        UpdateStream {
+            shared: Arc::new(Mutex::new(Shared {
+                state: NodeState::default(),
+                updates: broadcast::channel(STREAM_BUFFER).0,
+            })),
+        }
+    }
+}
+
+impl UpdateStream {
+    // Nobody listening is not an error
+    pub fn send(&self, update: UiUpdate) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.state.apply(&update);
+        let _ = shared.updates.send(update);
+    }
+
+    // Only the updates from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<UiUpdate> {
+        self.shared.lock().unwrap().updates.subscribe()
+    }
+
+    pub fn subscribe_with_state(&self) -> (Vec<UiUpdate>, broadcast::Receiver<UiUpdate>) {
+        let shared = self.shared.lock().unwrap();
+        (shared.state.updates(), shared.updates.subscribe())
+    }
+
+    pub fn topics(&self) -> Vec<String> {
+        self.shared.lock().unwrap().state.topics.clone()
+    }
+}
+
+// What a client needs to catch up, kept from the updates going by
+#[derive(Debug, Default)]
+struct NodeState {
+    topics: Vec<String>,
+    listen_addrs: Vec<ListenAddrInfo>,
+    dial_targets: Vec<DialTargetInfo>,
+    peers: HashMap<PeerId, PeerInfo>,
+    events: VecDeque<ScopeEvent>,
+}
+
+impl NodeState {
+    fn apply(&mut self, update: &UiUpdate) {
+        match update {
+            UiUpdate::Subscribed(topic) if !self.topics.contains(topic) => {
+                self.topics.push(topic.clone());
+            }
+            UiUpdate::Unsubscribed(topic) => self.topics.retain(|t| t != topic),
+            UiUpdate::ListenAddrChanged(info) => {
+                match self.listen_addrs.iter_mut().find(|a| {
+                    a.listener == info.listener && a.address == info.address
+                }) {
+                    Some(known) => *known = info.clone(),
+                    None => self.listen_addrs.push(info.clone()),
+                }
+            }
+            UiUpdate::DialTargetChanged(info) => {
+                match self.dial_targets.iter_mut().find(|t| t.address == info.address) {
+                    Some(known) => *known = info.clone(),
+                    None => self.dial_targets.push(info.clone()),
+                }
+            }
+            UiUpdate::DialTargetRemoved(address) => {
+                self.dial_targets.retain(|t| t.address != *address);
+            }
+            UiUpdate::PeerChanged(info) => {
+                self.peers.insert(info.peer_id, info.clone());
+            }
+            UiUpdate::PeerGone(peer_id) => {
+                self.peers.remove(peer_id);
+            }
+            UiUpdate::Event(event) => {
+                if self.events.len() == STATE_EVENTS {
+                    self.events.pop_front();
+                }
+                self.events.push_back(event.clone());
+            }
+            _ => {}
+        }
+    }
+
+    // The state as the updates that lead to it
+    fn updates(&self) -> Vec<UiUpdate> {
+        let topics = self.topics.iter().cloned().map(UiUpdate::Subscribed);
+        let listen_addrs = self.listen_addrs.iter().cloned().map(UiUpdate::ListenAddrChanged);
+        let dial_targets = self.dial_targets.iter().cloned().map(UiUpdate::DialTargetChanged);
+        let peers = self.peers.values().cloned().map(UiUpdate::PeerChanged);
+        let events = self.events.iter().cloned().map(UiUpdate::Event);
+        topics
+            .chain(listen_addrs)
+            .chain(dial_targets)
+            .chain(peers)
+            .chain(events)
+            .collect()
+    }
+}
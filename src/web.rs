@@ -0,0 +1,207 @@
+// Web dashboard frontend, served with --web when built with the web feature.
+// The page in web_dashboard.html gets the UiUpdate stream over a WebSocket at /ws, starting
+// with the state of the node, and sends UiCommands back as JSON like headless mode reads them.
+
+use std::error::Error;
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::frontend::{CommandSender, NodeInfo, ScopeFrontend};
+use crate::ui_api::{UiCommand, UiUpdate};
+use crate::update_stream::UpdateStream;
+
+const DASHBOARD: &str = include_str!("web_dashboard.html");
+
+pub struct WebFrontend {
+    address: SocketAddr,
+    updates: UpdateStream,
+    server: Option<JoinHandle<()>>,
+}
+
+impl WebFrontend {
+    pub fn new(address: SocketAddr) -> Self {
+        WebFrontend {
+            address,
+            updates: UpdateStream::default(),
+            server: None,
+        }
+    }
+}
+
+// What every page connection needs
+#[derive(Clone)]
+struct Dashboard {
+    commands: CommandSender,
+    updates: UpdateStream,
+    // The first update of every page, the TUI shows it in its instance info
+    greeting: UiUpdate,
+    // Host headers of requests meant for this dashboard
+    hosts: Vec<String>,
+}
+
+#[async_trait]
+impl ScopeFrontend for WebFrontend {
+    fn name(&self) -> &'static str {
+        "web dashboard"
+    }
+
+    async fn start(
+        &mut self,
+        node: &NodeInfo,
+        commands: CommandSender,
+    ) -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind(self.address)
+            .await
+            .map_err(|e| format!("Can not serve the web dashboard on {}: {e}", self.address))?;
+        let port = listener.local_addr()?.port();
+        let dashboard = Dashboard {
+            commands,
+            updates: self.updates.clone(),
+            greeting: UiUpdate::TerminalOutput(format!(
+                "Peer ID: {} Identity: {}",
+                node.peer_id, node.identity_source
+            )),
+            hosts: vec![
+                listener.local_addr()?.to_string(),
+                format!("localhost:{port}"),
+                format!("127.0.0.1:{port}"),
+            ],
+        };
+        let router = Router::new()
+            .route("/", get(|| async { Html(DASHBOARD) }))
+            .route("/ws", get(websocket))
+            .with_state(dashboard);
+        self.server = Some(tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        }));
+        Ok(())
+    }
+
+    fn update(&self, update: UiUpdate) -> Result<(), Box<dyn Error>> {
+        self.updates.send(update);
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+    }
+}
+
+async fn websocket(
+    upgrade: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(dashboard): State<Dashboard>,
+) -> Response {
+    if !same_origin(&headers, &dashboard.hosts) {
+        return (StatusCode::FORBIDDEN, "The dashboard only accepts its own page").into_response();
+    }
+    upgrade.on_upgrade(move |socket| serve(socket, dashboard))
+}
+
+// Browsers send the origin of the page, a page of another site must not drive the node.
+// Clients that are not browsers send no origin. The host has to be one of ours too, else a
+// site whose name was rebound to our address would count as the same origin.
+fn same_origin(headers: &HeaderMap, hosts: &[String]) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let origin_host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, host)| host);
+    let host = headers.get(header::HOST).and_then(|host| host.to_str().ok());
+    matches!((origin_host, host), (Some(origin_host), Some(host))
+        if origin_host == host && hosts.iter().any(|ours| ours == host))
+}
+
+// Commands that fail before they reach the node are only reported to this page
+async fn serve(mut socket: WebSocket, dashboard: Dashboard) {
+    let (state, mut updates) = dashboard.updates.subscribe_with_state();
+    for update in std::iter::once(dashboard.greeting.clone()).chain(state) {
+        if send(&mut socket, &update).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => continue,
+                };
+                let error = match serde_json::from_str::<UiCommand>(&text) {
+                    Ok(UiCommand::Shutdown) => {
+                        Some("The node can not be shut down from the web dashboard".to_string())
+                    }
+                    Ok(command) => dashboard.commands.send(command).await
+                        .err()
+                        .map(|_| "The node is shutting down".to_string()),
+                    Err(e) => Some(format!("Invalid command {text}: {e}")),
+                };
+                if let Some(error) = error {
+                    if send(&mut socket, &UiUpdate::CommandError(error)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            update = updates.recv() => {
+                let update = match update {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(skipped)) => UiUpdate::CommandError(
+                        format!("Missed {skipped} updates, reload the page to catch up")),
+                    Err(RecvError::Closed) => return,
+                };
+                if send(&mut socket, &update).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, update: &UiUpdate) -> Result<(), Box<dyn Error>> {
+    let text = serde_json::to_string(update)?;
+    socket.send(Message::Text(text)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(origin: Option<&str>, host: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(origin) = origin {
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+        }
+        headers.insert(header::HOST, host.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn only_pages_of_the_dashboard_are_accepted() {
+        let hosts = ["127.0.0.1:8080".to_string(), "localhost:8080".to_string()];
+        let accepted = |origin, host| same_origin(&headers(origin, host), &hosts);
+        assert!(accepted(Some("http://127.0.0.1:8080"), "127.0.0.1:8080"));
+        assert!(accepted(Some("http://localhost:8080"), "localhost:8080"));
+        assert!(accepted(None, "127.0.0.1:8080"));
+        assert!(!accepted(Some("http://evil.example"), "127.0.0.1:8080"));
+        // a rebound name sends the same origin and host
+        assert!(!accepted(Some("http://evil.example:8080"), "evil.example:8080"));
+        assert!(!accepted(Some("http://localhost:9090"), "localhost:9090"));
+    }
+}